@@ -10,7 +10,7 @@
  * See the License for the specific TON DEV software governing permissions and
  * limitations under the License.
  */
-use abi_json::json_abi::{encode_function_call, decode_function_response};
+use abi_json::json_abi::{encode_function_call, decode_function_response, decode_unknown_function_call};
 use abi_json::Contract;
 use failure::format_err;
 use sha2::{Digest, Sha256};
@@ -25,8 +25,12 @@ pub fn build_abi_body(
     internal: bool,
     address: Option<String>,
 ) -> Result<BuilderData> {
+    let abi_json = load_abi_json_string(abi_file)?;
+    if method == "constructor" && !load_abi_contract(&abi_json)?.functions().contains_key(method) {
+        return Err(format_err!("ABI has no constructor function"))
+    }
     encode_function_call(
-        load_abi_json_string(abi_file)?,
+        abi_json,
         method.to_owned(),
         header.map(|v| v.to_owned()),
         params.to_owned(),
@@ -36,6 +40,25 @@ pub fn build_abi_body(
     )
 }
 
+/// Default lifetime of external messages in seconds, used to set the `expire` header.
+pub const DEFAULT_EXPIRE_TTL: u32 = 40;
+
+/// Sets the `expire` header to `now + ttl` if the ABI declares it and it is not supplied in `header`.
+/// Returns the header JSON to pass to `build_abi_body`.
+pub fn header_with_expire(abi_json: &str, header: Option<&str>, now: u32, ttl: u32) -> Result<Option<String>> {
+    let contract = load_abi_contract(abi_json)?;
+    if !contract.header().iter().any(|param| param.name == "expire") {
+        return Ok(header.map(|v| v.to_owned()))
+    }
+    let mut values = match header {
+        Some(header) => serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(header)
+            .map_err(|e| format_err!("ABI header {} is not a JSON object: {}", header, e))?,
+        None => serde_json::Map::new(),
+    };
+    values.entry("expire").or_insert_with(|| now.saturating_add(ttl).into());
+    Ok(Some(serde_json::Value::Object(values).to_string()))
+}
+
 pub fn load_abi_json_string(abi_file: &str) -> Result<String> {
     std::fs::read_to_string(abi_file)
         .map_err(|e| format_err!("unable to read ABI file {}: {}", abi_file, e))
@@ -61,21 +84,131 @@ pub fn decode_body(
     )
 }
 
-pub fn gen_abi_id(mut abi: Option<Contract>, func_name: &str) -> u32 {
-    if let Some(ref mut contract) = abi {
-        let functions = contract.functions();
-        let events = contract.events();
-        functions.get(func_name).map(|f| f.get_input_id())
-            .or_else(|| events.get(func_name).map(|e| e.get_function_id()))
-            .unwrap_or_else(|| calc_func_id(func_name))
-    } else {
-        calc_func_id(func_name)
+const XMODEM: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM);
+
+/// Scheme of id calculation for public functions missing in the ABI.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum MethodIdScheme {
+    /// First 4 bytes of sha256 of the function name.
+    #[default]
+    NameHash,
+    /// FunC get-method ids: CRC-16/XMODEM of the function name with bit 0x10000 set.
+    Crc16,
+}
+
+impl std::str::FromStr for MethodIdScheme {
+    type Err = failure::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name_hash" => Ok(MethodIdScheme::NameHash),
+            "crc16" => Ok(MethodIdScheme::Crc16),
+            _ => Err(format_err!("unknown method id scheme {}, expected one of: name_hash, crc16", s)),
+        }
     }
 }
 
+fn abi_id(abi: Option<Contract>, func_name: &str) -> Option<u32> {
+    abi.and_then(|contract| {
+        contract.functions().get(func_name).map(|f| f.get_input_id())
+            .or_else(|| contract.events().get(func_name).map(|e| e.get_function_id()))
+    })
+}
+
+pub fn gen_abi_id(abi: Option<Contract>, func_name: &str) -> u32 {
+    abi_id(abi, func_name).unwrap_or_else(|| calc_func_id(func_name))
+}
+
+pub fn gen_method_id(abi: Option<Contract>, func_name: &str, scheme: MethodIdScheme) -> u32 {
+    abi_id(abi, func_name).unwrap_or_else(|| match scheme {
+        MethodIdScheme::NameHash => calc_func_id(func_name),
+        MethodIdScheme::Crc16 => (XMODEM.checksum(func_name.as_bytes()) as u32) | 0x10000,
+    })
+}
+
 fn calc_func_id(func_interface: &str) -> u32 {
     let mut id_bytes = [0u8; 4];
     let hash = Sha256::digest(func_interface.as_bytes());
     id_bytes.copy_from_slice(&hash[..4]);
     u32::from_be_bytes(id_bytes)
 }
+
+/// Decodes a function call body, returns the function name and its parameters in JSON.
+pub fn decode_call_body(
+    abi_file: &str,
+    body: SliceData,
+    internal: bool,
+) -> Result<(String, String)> {
+    let decoded = decode_unknown_function_call(
+        load_abi_json_string(abi_file)?,
+        body,
+        internal,
+        false,
+    )?;
+    Ok((decoded.function_name, decoded.params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_signed_body_v2_3() {
+        let abi = "tests/test_abi23.abi.json";
+        let body = |address: &str| {
+            let secret = ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap();
+            let public = ed25519_dalek::PublicKey::from(&secret);
+            let keypair = ed25519_dalek::Keypair { secret, public };
+            build_abi_body(abi, "constructor", "{}", None, Some(keypair), false, Some(address.to_string()))
+                .unwrap().into_cell().unwrap()
+        };
+        // ABI 2.3 signs the destination address together with the body
+        let body1 = body(&format!("0:{}", "11".repeat(32)));
+        let body2 = body(&format!("0:{}", "22".repeat(32)));
+        assert_ne!(body1, body2);
+        let (name, _) = decode_call_body(abi, SliceData::load_cell(body1).unwrap(), false).unwrap();
+        assert_eq!(name, "constructor");
+    }
+
+    #[test]
+    fn test_header_with_expire() {
+        let abi = r#"{"ABI version": 2, "version": "2.2", "header": ["time", "expire"], "functions": [], "data": [], "events": [], "fields": []}"#;
+        let header = header_with_expire(abi, None, 100, 40).unwrap().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&header).unwrap(), serde_json::json!({"expire": 140}));
+        let header = header_with_expire(abi, Some(r#"{"time": 5, "expire": 7}"#), 100, 40).unwrap().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&header).unwrap(), serde_json::json!({"time": 5, "expire": 7}));
+        assert!(header_with_expire(abi, Some("5"), 100, 40).is_err());
+
+        let abi = load_abi_json_string("tests/test_complex.abi.json").unwrap();
+        assert_eq!(header_with_expire(&abi, None, 100, 40).unwrap(), None);
+    }
+
+    #[test]
+    fn test_missing_constructor() {
+        let err = build_abi_body("tests/test_public.abi.json", "constructor", "{}", None, None, false, None).unwrap_err();
+        assert_eq!(err.to_string(), "ABI has no constructor function");
+        assert!(build_abi_body("tests/Wallet.abi.json", "constructor", "{}", None, None, false, None).is_ok());
+    }
+
+    #[test]
+    fn test_complex_types_round_trip() {
+        let abi = "tests/test_complex.abi.json";
+        let params = r#"{
+            "values": {
+                "1": { "a": "7", "b": ["1", "2", "18446744073709551615"] },
+                "100": { "a": "0", "b": [] }
+            },
+            "pairs": [
+                { "x": true, "y": "-5" },
+                { "x": false, "y": "32767" }
+            ]
+        }"#;
+        let body = build_abi_body(abi, "setValues", params, None, None, true, None).unwrap();
+        let (name, decoded) = decode_call_body(abi, SliceData::load_builder(body).unwrap(), true).unwrap();
+        assert_eq!(name, "setValues");
+        assert_eq!(
+            serde_json::from_str::<Value>(&decoded).unwrap(),
+            serde_json::from_str::<Value>(params).unwrap()
+        );
+    }
+}
@@ -12,6 +12,7 @@
  */
 
 use std::collections::HashMap;
+use ton_labs_assembler::DbgInfo;
 use ton_types::{Cell, HashmapE, HashmapType, Result, SliceData, fail};
 use super::{
     types::{Instruction, InstructionParameter, Code},
@@ -19,8 +20,12 @@ use super::{
 };
 
 fn match_dictpushconst_dictugetjmp(pair: &mut [Instruction]) -> Option<&mut Vec<InstructionParameter>> {
+    match_dictpushconst_jmp(pair, "DICTUGETJMP")
+}
+
+fn match_dictpushconst_jmp<'a>(pair: &'a mut [Instruction], jmp: &str) -> Option<&'a mut Vec<InstructionParameter>> {
     let insn2 = pair.get(1)?.name();
-    if insn2 != "DICTUGETJMP" {
+    if insn2 != jmp {
         return None
     }
     let insn1 = pair.get_mut(0)?;
@@ -40,6 +45,14 @@ impl Code {
         }
     }
 
+    fn process_dictpushconst_dictugetjmpz(code: &mut Code) {
+        for pair in code.chunks_mut(2) {
+            if let Some(params) = match_dictpushconst_jmp(pair, "DICTUGETJMPZ") {
+                params.push(InstructionParameter::CodeDictMarker)
+            }
+        }
+    }
+
     fn traverse_code_tree(&mut self, process: fn(&mut Code)) {
         let mut stack = vec!(self);
         while let Some(code) = stack.pop() {
@@ -58,6 +71,12 @@ impl Code {
     pub fn elaborate_dictpushconst_dictugetjmp(&mut self) {
         self.traverse_code_tree(Self::process_dictpushconst_dictugetjmp)
     }
+
+    /// Same as `elaborate_dictpushconst_dictugetjmp`, but for the selector of the linker, which
+    /// jumps with DICTUGETJMPZ.
+    pub fn elaborate_dictpushconst_dictugetjmpz(&mut self) {
+        self.traverse_code_tree(Self::process_dictpushconst_dictugetjmpz)
+    }
 }
 
 pub(super) struct DelimitedHashmapE {
@@ -138,7 +157,7 @@ impl DelimitedHashmapE {
         }
         Ok(())
     }
-    fn print_impl(&self, cell: &Cell, indent: &str, path: Vec<u8>) -> String {
+    fn print_impl(&self, cell: &Cell, indent: &str, path: Vec<u8>, debug_info: Option<&DbgInfo>) -> String {
         let mut text = String::new();
         text += &format!("{}.cell ", indent);
         text += &format!("{{ ;; #{}\n", cell.repr_hash().to_hex_string());
@@ -148,7 +167,7 @@ impl DelimitedHashmapE {
             let aux = slice.get_next_slice(*offset).unwrap();
             text += &format!("{}.blob x{}\n", inner_indent, aux.to_hex_string());
             text += &format!("{};; method {}\n", inner_indent, id);
-            text += &code.print(&inner_indent, true, 0);
+            text += &code.print_ex(&inner_indent, true, 0, debug_info);
         } else {
             if slice.remaining_bits() > 0 {
                 text += &format!("{}.blob x{}\n", inner_indent, slice.to_hex_string());
@@ -156,13 +175,13 @@ impl DelimitedHashmapE {
             for i in 0..cell.references_count() {
                 let mut path = path.clone();
                 path.push(i as u8);
-                text += &self.print_impl(&cell.reference(i).unwrap(), inner_indent.as_str(), path);
+                text += &self.print_impl(&cell.reference(i).unwrap(), inner_indent.as_str(), path, debug_info);
             }
         }
         text += &format!("{}}}\n", indent);
         text
     }
-    pub fn print(&self, indent: &str) -> String {
-        self.print_impl(self.dict.data().unwrap(), indent, vec!())
+    pub fn print(&self, indent: &str, debug_info: Option<&DbgInfo>) -> String {
+        self.print_impl(self.dict.data().unwrap(), indent, vec!(), debug_info)
     }
 }
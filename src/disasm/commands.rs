@@ -17,7 +17,8 @@ use failure::{bail, format_err};
 use ton_block::Serializable;
 use clap::ArgMatches;
 use ton_types::read_boc;
-use ton_types::{Cell, HashmapE, HashmapType, SliceData, UInt256, Status};
+use ton_labs_assembler::DbgInfo;
+use ton_types::{Cell, HashmapE, HashmapType, Result, SliceData, UInt256, Status};
 
 use super::{loader::Loader, types::Shape};
 
@@ -318,6 +319,15 @@ pub(super) fn disasm(slice: &mut SliceData) -> String {
     disasm_ex(slice, false)
 }
 
+/// Disassembles the code annotating the instructions with their source positions from `debug_info`.
+pub fn disasm_annotated(slice: &mut SliceData, debug_info: &DbgInfo) -> Result<String> {
+    let mut loader = Loader::new(false);
+    let mut code = loader.load(slice, false)?;
+    code.elaborate_dictpushconst_dictugetjmp();
+    code.elaborate_dictpushconst_dictugetjmpz();
+    Ok(code.print_ex("", true, 0, Some(debug_info)))
+}
+
 pub(super) fn disasm_ex(slice: &mut SliceData, collapsed: bool) -> String {
     let mut loader = Loader::new(collapsed);
     let mut code = loader.load(slice, false).unwrap();
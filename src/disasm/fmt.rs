@@ -11,19 +11,20 @@
  * limitations under the License.
  */
 
+use ton_labs_assembler::DbgInfo;
 use ton_types::{Cell, Result, SliceData};
 use super::{
     types::{Instruction, InstructionParameter, Code},
     codedict::DelimitedHashmapE
 };
 
-fn print_code_dict(cell: &Cell, key_size: usize, indent: &str) -> Result<String> {
+fn print_code_dict(cell: &Cell, key_size: usize, indent: &str, debug_info: Option<&DbgInfo>) -> Result<String> {
     let mut map = DelimitedHashmapE::new(cell.clone(), key_size);
     map.mark()?;
-    Ok(map.print(indent))
+    Ok(map.print(indent, debug_info))
 }
 
-fn print_dictpushconst(insn: &Instruction, indent: &str) -> String {
+fn print_dictpushconst(insn: &Instruction, indent: &str, debug_info: Option<&DbgInfo>) -> String {
     let key_length = if let Some(InstructionParameter::Length(l)) = insn.params().get(0) {
         *l
     } else {
@@ -37,7 +38,7 @@ fn print_dictpushconst(insn: &Instruction, indent: &str) -> String {
     };
     if let Some(cell) = cell {
         let text = if let Some(InstructionParameter::CodeDictMarker) = insn.params().get(2) {
-            print_code_dict(cell, key_length, indent)
+            print_code_dict(cell, key_length, indent, debug_info)
                 .unwrap_or_else(|_| print_cell(cell, indent, true))
         } else {
             print_cell(cell, indent, true)
@@ -92,8 +93,20 @@ fn print_bytecode(slice: Option<(&SliceData, usize)>, bytecode_width: usize) ->
     text
 }
 
+/// Source position of the instruction from the debug map, e.g. `Wallet.code:12`.
+fn print_source(insn: &Instruction, debug_info: Option<&DbgInfo>) -> Option<String> {
+    let bytecode = insn.bytecode()?;
+    let pos = debug_info?.get(&bytecode.cell().repr_hash())?.get(&bytecode.pos())?;
+    Some(format!("{}:{}", pos.filename, pos.line))
+}
+
 impl Code {
     pub fn print(&self, indent: &str, full: bool, bytecode_width: usize) -> String {
+        self.print_ex(indent, full, bytecode_width, None)
+    }
+
+    /// Same as `print`, but instructions found in `debug_info` are followed by their source position.
+    pub fn print_ex(&self, indent: &str, full: bool, bytecode_width: usize, debug_info: Option<&DbgInfo>) -> String {
         let mut text = String::new();
         for insn in self.iter() {
             text += &print_bytecode(insn.bytecode().map(|v| (v, insn.refs())), bytecode_width);
@@ -102,7 +115,7 @@ impl Code {
                 match insn.name() {
                     "DICTPUSHCONST" | "PFXDICTSWITCH" => {
                         // TODO better improve assembler for these two insns
-                        text += &print_dictpushconst(insn, indent);
+                        text += &print_dictpushconst(insn, indent, debug_info);
                         continue
                     }
                     "IMPLICIT-JMP" => {
@@ -110,7 +123,7 @@ impl Code {
                             let hash = cell.as_ref().unwrap().repr_hash().to_hex_string();
                             text += &format!(".cell {{ ;; #{}\n", hash);
                             let inner_indent = String::from("  ") + indent;
-                            text += &code.print(&inner_indent, full, bytecode_width);
+                            text += &code.print_ex(&inner_indent, full, bytecode_width, debug_info);
                             text += indent;
                             text += "}\n";
                         } else {
@@ -125,17 +138,20 @@ impl Code {
             if insn.is_quiet() {
                 text += "Q";
             }
-            text += &print_insn_params(insn.params(), indent, full, bytecode_width);
+            text += &print_insn_params(insn.params(), indent, full, bytecode_width, debug_info);
             if let Some(comment) = insn.comment() {
                 text += &format!(" ;; {}", comment);
             }
+            if let Some(source) = print_source(insn, debug_info) {
+                text += &format!(" ;; {}", source);
+            }
             text += "\n";
         }
         text
     }
 }
 
-fn print_insn_params(params: &Vec<InstructionParameter>, indent: &str, full: bool, bytecode_width: usize) -> String {
+fn print_insn_params(params: &Vec<InstructionParameter>, indent: &str, full: bool, bytecode_width: usize, debug_info: Option<&DbgInfo>) -> String {
     use InstructionParameter::*;
 
     let mut text = String::new();
@@ -193,7 +209,7 @@ fn print_insn_params(params: &Vec<InstructionParameter>, indent: &str, full: boo
                         text += "{\n";
                     }
                     let inner_indent = String::from("  ") + indent;
-                    text += &code.print(&inner_indent, full, bytecode_width);
+                    text += &code.print_ex(&inner_indent, full, bytecode_width, debug_info);
                     text += &print_bytecode(None, bytecode_width);
                     text += indent;
                     text += "}";
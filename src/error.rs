@@ -0,0 +1,43 @@
+/*
+ * Copyright 2018-2022 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::fmt;
+
+/// Kinds of errors reported by the linker. The errors are returned wrapped into `failure::Error`,
+/// use `err.downcast_ref::<LinkerError>()` to match on them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkerError {
+    /// The assembler sources can't be parsed, `line` is 1-based.
+    ParseError { file: String, line: usize, message: String },
+    /// The assembler sources can't be compiled or linked.
+    CompileError(String),
+    /// A file can't be read or written.
+    IoError(String),
+    /// A file doesn't contain a valid bag of cells or the expected cells.
+    InvalidBoc(String),
+    /// The compiled contract violates the network limits.
+    LimitExceeded(String),
+}
+
+impl fmt::Display for LinkerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkerError::ParseError { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+            LinkerError::CompileError(msg) |
+            LinkerError::IoError(msg) |
+            LinkerError::InvalidBoc(msg) |
+            LinkerError::LimitExceeded(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl failure::Fail for LinkerError {}
@@ -31,6 +31,12 @@ impl KeypairManager {
             .map_err(|e| format_err!("failed to decode private key: {}", e))?;
         let mut public = hex::decode(keys.public)
             .map_err(|e| format_err!("failed to decode public key: {}", e))?;
+        // a mismatched pair would give a contract nobody can sign messages for
+        let secret = ed25519_dalek::SecretKey::from_bytes(&keypair)
+            .map_err(|e| format_err!("failed to decode private key: {}", e))?;
+        if ed25519_dalek::PublicKey::from(&secret).as_bytes()[..] != public[..] {
+            return Err(format_err!("public key in {} doesn't match the secret key", filename))
+        }
         keypair.append(&mut public);
         ed25519_dalek::Keypair::from_bytes(&keypair)
             .map_err(|e| format_err!("failed to generate keypair: {}", e))
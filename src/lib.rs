@@ -37,6 +37,7 @@ extern crate ton_vm;
 
 pub mod abi;
 pub mod disasm;
+pub mod error;
 pub mod keyman;
 pub mod parser;
 pub mod printer;
@@ -36,6 +36,7 @@ extern crate ton_labs_assembler;
 extern crate num_traits;
 
 mod abi;
+mod error;
 mod keyman;
 mod parser;
 mod printer;
@@ -45,27 +46,25 @@ mod methdict;
 mod testcall;
 mod disasm;
 
-use abi::{build_abi_body, decode_body, load_abi_json_string, load_abi_contract};
+use abi::{build_abi_body, decode_body, gen_method_id, header_with_expire, DEFAULT_EXPIRE_TTL, load_abi_json_string, load_abi_contract, MethodIdScheme};
 use clap::ArgMatches;
 use failure::{format_err, bail};
 use keyman::KeypairManager;
-use parser::{ParseEngine, ParseEngineResults};
-use program::{Program, get_now, save_to_file, load_from_file};
+use parser::{ParseEngine, ParseEngineResults, STDIN_PATH};
+use program::{Program, SelectorKind, SerializationFormat, Verbosity, get_now, save_to_file, load_from_file, save_account_to_file, update_account_state, recompute_address, verify_against};
 use resolver::resolve_name;
 use ton_block::{Deserializable, Message, StateInit, Serializable, Account, MsgAddressInt, ExternalInboundMessageHeader, InternalMessageHeader,
-                MsgAddressIntOrNone, ConfigParams};
+                MsgAddressIntOrNone};
 use std::io::Write;
-use std::{path::Path};
-use testcall::{call_contract, MsgInfo, TestCallParams, TraceLevel};
-use ton_types::{SliceData, Result, Status, AccountId, UInt256, BocWriter};
+use std::path::{Path, PathBuf};
+use testcall::{call_contract, config_capabilities, replay_transaction, Capabilities, GasLimit, MsgInfo, TestCallParams, TraceLevel, VmVersion};
+use ton_types::{SliceData, Result, Status, AccountId, BocWriter, UInt256};
 use std::env;
 use disasm::commands::disasm_command;
 use ton_labs_assembler::{Line, compile_code_to_cell};
 use std::fs::File;
 use std::str::FromStr;
 
-const DEFAULT_CAPABILITIES:u64 = 0x42E | 0x800000;   // STCONT turned on
-
 fn main() -> std::result::Result<(), i32> {
     linker_main().map_err(|err_str| {
         println!("Error: {}", err_str);
@@ -91,6 +90,10 @@ fn linker_main() -> Status {
             (author: "TON Labs")
             (@arg INPUT: +required +takes_value "BOC file")
             (@arg TVC: --tvc "BOC file is tvc file")
+            (@arg TLB: --tlb requires[TVC] "Prints the state with fields named according to the StateInit TL-B scheme and all its cells")
+            (@arg METHODS: --methods requires[TVC] conflicts_with[TLB] "Prints ids of the methods present in the contract code")
+            (@arg RAW_ADDRESSES: --("raw-addresses") "Prints message addresses in raw workchain:hex form")
+            (@arg TOKEN: --token +takes_value "Prints nanogram values of the message also in the given unit, e.g. TON:9 (symbol and count of decimals)")
         )
         (@subcommand replace_code =>
             (@setting AllowNegativeNumbers)
@@ -105,22 +108,54 @@ fn linker_main() -> Status {
             (@arg OUT_FILE: -o +takes_value "Output file name. If not specified the input file is rewritten.")
             (@arg TVC: --tvc "Changes command behaviour to work with stateInit TVC instead of account BOC.")
         )
+        (@subcommand verify =>
+            (about: "Compile assembler source and check that its code hash matches the code of a tvc file.")
+            (version: build_info.as_str())
+            (author: "TON Labs")
+            (@arg INPUT: +required +takes_value "TVM assembler source file")
+            (@arg TVC: +required +takes_value "Path to the tvc file to verify")
+            (@arg ABI: -a --("abi-json") +takes_value "Supplies contract abi to calculate correct function ids. If not specified abi can be loaded from file path obtained from <INPUT> path if it exists.")
+            (@arg LIB: --lib +takes_value ... number_of_values(1) "Standard library source file. If not specified lib is loaded from environment variable TVM_LINKER_LIB_PATH if it exists.")
+        )
+        (@subcommand address =>
+            (@setting AllowNegativeNumbers)
+            (about: "Print raw and user-friendly addresses of a contract from a tvc file.")
+            (version: build_info.as_str())
+            (author: "TON Labs")
+            (@arg TVC: +required +takes_value "Path to the tvc file")
+            (@arg WC: -w +takes_value "Workchain id of the address, -1 by default")
+        )
         (@subcommand compile =>
             (@setting AllowNegativeNumbers)
             (about: "compile contract")
             (version: build_info.as_str())
             (author: "TON Labs")
-            (@arg INPUT: +required +takes_value "TVM assembler source file")
+            (@arg INPUT: +required +takes_value "TVM assembler source file, use - to read it from the standard input")
             (@arg ABI: -a --("abi-json") +takes_value "Supplies contract abi to calculate correct function ids. If not specified abi can be loaded from file path obtained from <INPUT> path if it exists.")
-            (@arg WC: -w +takes_value "Workchain id used to print contract address, -1 by default.")
+            (@arg WC: -w +takes_value ... number_of_values(1) "Workchain id used to print contract address, -1 by default. Can be repeated to print addresses for several workchains.")
             (@arg DEBUG: --debug "Prints debug info: xref table and parsed assembler sources")
             (@arg DEBUG_MAP: --("debug-map") +takes_value "Generates debug map file")
             (@arg DATA: --("data") +takes_value "Overwrites data with a cell from a file")
+            (@arg MERGE_DATA: --("merge-data") requires[DATA] "Merges data dictionary from the --data file into the contract data instead of overwriting it")
             (@arg LIB: --lib +takes_value ... number_of_values(1) "Standard library source file. If not specified lib is loaded from environment variable TVM_LINKER_LIB_PATH if it exists.")
             (@arg OUT_FILE: -o +takes_value "Output file name")
             (@arg LANGUAGE: --language +takes_value "Enable language-specific features in linkage")
             (@arg PRINT_CODE: --print_code "Command will only print the code cell without generating the TVC file")
             (@arg SILENT: --silent "Command will print necessary output")
+            (@arg QUIET: --quiet conflicts_with[SILENT] "Command will print nothing but errors")
+            (@arg JSON: --json conflicts_with[PRINT_CODE] "Command will print a compilation summary in JSON format")
+            (@arg BOC_FORMAT: --("boc-format") +takes_value "Flags of the TVC bag of cells: default (no index and CRC, as Fift's boc>B), crc (as 2 boc+>B), index_crc (as 3 boc+>B) or fift (the same as index_crc)")
+            (@arg CHECK_ABI: --("check-abi") "Fails if a function of the ABI is not implemented in the sources, warns about public functions missing in the ABI")
+            (@arg CODE_SALT: --("code-salt") +takes_value "Salts the code with the cell from a BOC file, the salt is stored after the contract version")
+            (@arg DEPLOY_CODE: --("deploy-code") +takes_value "Deploys the contract with the code cell from a BOC file, e.g. a loader, and appends the compiled code to the data cell")
+            (@arg EXTERNAL_METHOD: --("external-method") +takes_value ... number_of_values(1) "Links an already compiled method: <name_or_id>=<boc_file> or <name_or_id>=lib:<hash> for a library reference")
+            (@arg MAX_CELL_DEPTH: --("max-cell-depth") +takes_value "Maximum depth of code and data cells, 1024 (the network limit) by default")
+            (@arg PROFILE: --profile "Prints elapsed time of compilation stages")
+            (@arg SELECTOR: --selector +takes_value "Layout of the code selector: auto (legacy if the sources have a .selector section), legacy or modern")
+            (@arg METHOD_ID_SCHEME: --("method-id-scheme") +takes_value "Calculation of ids of public functions missing in the ABI: name_hash (sha256 of the name) or crc16 (as FunC get-methods), overrides .pragma method-id-crc16")
+            (@arg SIZE: --size conflicts_with[PRINT_CODE] "Prints the size of the contract state: BOC bytes, cells and bits")
+            (@arg SPLIT_BOC: --("split-boc") "Additionally saves code and data cells to <name>.code.boc and <name>.data.boc")
+            (@arg STDOUT_HEX: --("stdout-hex") conflicts_with[PRINT_CODE] "Additionally prints the saved BOC as a hex string")
             (@arg RAW: --raw "Assemble code as-is into a BOC")
         )
         (@subcommand test =>
@@ -134,15 +169,23 @@ fn linker_main() -> Status {
             (@arg SIGN: --sign +takes_value "Signs body with private key from defined file")
             (@arg TRACE: --trace "Prints last command name, stack and registers after each executed TVM command")
             (@arg TRACE_MIN: --("trace-minimal") "Prints minimal trace")
+            (@arg TRACE_STACK_DIFF: --("trace-stack-diff") conflicts_with[TRACE_MIN] "Same as --trace, but prints only the stack items changed by each command")
+            (@arg TRACE_METHOD: --("trace-method") +takes_value requires[SOURCE] "Prints the trace only for the commands of the given function, requires the source and the debug map")
+            (@arg TRACE_REGISTERS: --("trace-registers") "Prints control registers c4, c5 and c7 after each executed TVM command")
             (@arg DECODEC6: --("decode-c6") "Prints last command name, stack and registers after each executed TVM command")
             (@arg INTERNAL: --internal +takes_value "Emulates inbound internal message with value instead of external message")
             (@arg BOUNCED: --bounced requires[INTERNAL] "Emulates bounced message, can be used only with --internal option.")
             (@arg BALANCE: --balance +takes_value "Emulates supplied account balance")
             (@arg SRCADDR: --src +takes_value "Supplies message source address")
             (@arg NOW: --now +takes_value "Supplies transaction creation unixtime")
+            (@arg BLOCK_TIME: --("block-time") +takes_value "Overrides the block unixtime seen by the contract, the message keeps the time of --now")
+            (@arg GLOBAL_ID: --("global-id") +takes_value allow_hyphen_values(true) "Sets the global id of the network seen by the contract in the config param 19")
             (@arg TICKTOCK: --ticktock +takes_value conflicts_with[BODY] "Emulates ticktock transaction in masterchain, 0 for tick and -1 for tock")
             (@arg GASLIMIT: -l --("gas-limit") +takes_value "Defines gas limit for tvm execution")
+            (@arg GASCREDIT: --("gas-credit") +takes_value conflicts_with[GASLIMIT] conflicts_with[INTERNAL] "Defines free gas credit for external message, the gas limit is set to the maximum after ACCEPT")
             (@arg CONFIG: --config +takes_value "Imports config parameters from a config contract TVC")
+            (@arg CAPABILITIES: --capabilities +takes_value "Overrides VM capabilities with a comma-separated list of names (e.g. CapMycode,CapInitCodeHash) or a 0x-prefixed mask")
+            (@arg VM_VERSION: --("vm-version") +takes_value "Pins the VM behaviour and gas model: initial, 2022 or current")
             (@arg INPUT: +required +takes_value "TVM assembler source file or contract name if used with test subcommand")
             (@arg ADDRESS: --address +takes_value "Contract address, which can be obtained from the contract with `address(this)`. If not specified address can be obtained from the INPUT argument or set to zero.")
             (@arg DEBUG_MAP: -d --("debug-map") +takes_value "Supplies debug info json file")
@@ -150,6 +193,19 @@ fn linker_main() -> Status {
             (@arg ABI_METHOD: -m --("abi-method") +takes_value conflicts_with[BODY] "Supplies the name of the calling contract method")
             (@arg ABI_PARAMS: -p --("abi-params") +takes_value conflicts_with[BODY] "Supplies ABI arguments for the contract method (can be passed via filename). Can be not specified for empty parameters.")
             (@arg ABI_HEADER: -r --("abi-header") +takes_value conflicts_with[BODY] conflicts_with[INTERNAL] "Supplies ABI header")
+            (@arg EXPIRE_TTL: --("expire-ttl") +takes_value "Lifetime of the message in seconds, sets the expire header if the ABI declares it (40 by default)")
+        )
+        (@subcommand replay =>
+            (about: "rerun a transaction from a dumped account state and inbound message")
+            (version: build_info.as_str())
+            (author: "TON Labs")
+            (@arg ACCOUNT: +required +takes_value "Path to the file with the BOC of account state")
+            (@arg MESSAGE: +required +takes_value "Path to the file with the BOC of inbound message")
+            (@arg CONFIG: --config +takes_value "Imports config parameters from a config contract TVC")
+            (@arg TRACE: --trace "Prints last command name, stack and registers after each executed TVM command")
+            (@arg TRACE_MIN: --("trace-minimal") "Prints minimal trace")
+            (@arg TRACE_STACK_DIFF: --("trace-stack-diff") conflicts_with[TRACE_MIN] "Same as --trace, but prints only the stack items changed by each command")
+            (@arg OUT_FILE: -o +takes_value "Saves the account state after the transaction to the file")
         )
         (@subcommand message =>
             (@setting AllowNegativeNumbers)
@@ -164,8 +220,9 @@ fn linker_main() -> Status {
             (@arg ABI_METHOD: -m --("abi-method") +takes_value conflicts_with[DATA] "Supplies the name of the calling contract method")
             (@arg ABI_PARAMS: -p --("abi-params") +takes_value conflicts_with[DATA] "Supplies ABI arguments for the contract method")
             (@arg ABI_HEADER: -r --("abi-header") +takes_value conflicts_with[DATA] "Supplies ABI header")
+            (@arg EXPIRE_TTL: --("expire-ttl") +takes_value "Lifetime of the message in seconds, sets the expire header if the ABI declares it (40 by default)")
             (@arg SIGN: --setkey +takes_value "Loads existing keypair from the file")
-            (@arg ADDRESS: --addr +takes_value "Optional destination address to support ABI 2.3")
+            (@arg ADDRESS: --addr +takes_value "Destination address signed with the body in ABI 2.3, the contract address by default")
             (@arg INPUT: +required +takes_value "TVM assembler source file or contract name")
         )
         (@subcommand disasm =>
@@ -204,11 +261,31 @@ fn linker_main() -> Status {
         return run_test_subcmd(test_matches);
     }
 
+    //SUBCOMMAND REPLAY
+    if let Some(replay_matches) = matches.subcommand_matches("replay") {
+        return run_replay_subcmd(replay_matches);
+    }
+
     //SUBCOMMAND DECODE
     if let Some(decode_matches) = matches.subcommand_matches("decode") {
+        let mut options = printer::MsgPrinterOptions {
+            raw_addresses: decode_matches.is_present("RAW_ADDRESSES"),
+            ..Default::default()
+        };
+        if let Some(token) = decode_matches.value_of("TOKEN") {
+            options.token = Some(printer::Token::from_str(token)?);
+        }
+        if decode_matches.is_present("METHODS") {
+            for id in program::load_method_ids(decode_matches.value_of("INPUT").unwrap())? {
+                println!("{} (0x{:08x})", id, id);
+            }
+            return Ok(())
+        }
         return decode_boc(
             decode_matches.value_of("INPUT").unwrap(),
             decode_matches.is_present("TVC"),
+            decode_matches.is_present("TLB"),
+            &options,
         );
     }
 
@@ -232,7 +309,13 @@ fn linker_main() -> Status {
                 Some(body)
             },
             None => {
-                build_body(msg_matches, msg_matches.value_of("ADDRESS").map(|s| s.to_string()))?
+                // ABI 2.3 signs the body together with the destination address
+                let input = msg_matches.value_of("INPUT").unwrap();
+                let address = msg_matches.value_of("ADDRESS").map(|s| s.to_string()).or_else(|| {
+                    hex::decode(input).ok()
+                        .map(|_| format!("{}:{}", msg_matches.value_of("WORKCHAIN").unwrap_or("-1"), input))
+                });
+                build_body(msg_matches, address, get_now())?
             },
         };
 
@@ -261,46 +344,27 @@ fn linker_main() -> Status {
             file.write_all(&bytes)?;
             return Ok(())
         }
-        let abi_from_input = format!("{}{}", input.trim_end_matches("code"), "abi.json");
-        let silent = compile_matches.is_present("SILENT");
-        let abi_file = compile_matches.value_of("ABI").or_else(|| {
-            if !silent {
-                println!("ABI_PATH (obtained from INPUT): {}", abi_from_input);
-            }
-            Some(abi_from_input.as_ref())
-        });
-        let abi_json = match abi_file {
-            Some(abi_file_name) => Some(load_abi_json_string(abi_file_name)?),
-            None => None
+        let quiet = compile_matches.is_present("QUIET") || compile_matches.is_present("JSON");
+        let silent = quiet || compile_matches.is_present("SILENT");
+        let (abi_json, sources) = collect_sources(compile_matches, silent)?;
+        let abi_contract = match abi_json {
+            Some(ref abi_json) if compile_matches.is_present("EXTERNAL_METHOD") => Some(load_abi_contract(abi_json)?),
+            _ => None
         };
-        let mut sources = Vec::new();
-        for lib in compile_matches.values_of("LIB").unwrap_or_default() {
-            let path = Path::new(lib);
-            if !path.exists() {
-                bail!("File {} doesn't exist", lib);
-            }
-            sources.push(path);
-        }
-        let env_lib = env::var("TVM_LINKER_LIB_PATH").unwrap_or_default();
-        if sources.is_empty() && !env_lib.is_empty() {
-            if !silent {
-                println!("TVM_LINKER_LIB_PATH: {:?}", &env_lib);
-            }
-            let path = Path::new(&env_lib);
-            if !path.exists() {
-                bail!("File {} doesn't exist", &env_lib);
-            }
-            sources.push(path);
-        }
-
-        let path = Path::new(input);
-        if !path.exists() {
-            bail!("File {} doesn't exist", input);
-        }
-        sources.push(path);
+        let sources = sources.iter().map(|path| path.as_path()).collect();
+        let profile = compile_matches.is_present("PROFILE");
+        let start = std::time::Instant::now();
         let mut prog = Program::new(
             ParseEngine::new(sources, abi_json)?
         )?;
+        let method_id_scheme = compile_matches.value_of("METHOD_ID_SCHEME").map(MethodIdScheme::from_str).transpose()?;
+        if let Some(scheme) = method_id_scheme {
+            prog.set_method_id_scheme(scheme)?;
+        }
+        if profile {
+            eprintln!("Profile: parsing: {:?}", start.elapsed());
+        }
+        prog.set_profile(profile);
 
         let debug = compile_matches.is_present("DEBUG");
         prog.set_language(compile_matches.value_of("LANGUAGE"));
@@ -309,9 +373,12 @@ fn linker_main() -> Status {
            prog.debug_print();
         }
 
-        let wc = compile_matches.value_of("WC")
+        let mut workchains: Vec<i8> = compile_matches.values_of("WC").unwrap_or_default()
             .map(|wc| wc.parse::<i8>().unwrap_or(-1))
-            .unwrap_or(-1);
+            .collect();
+        if workchains.is_empty() {
+            workchains.push(-1);
+        }
 
         let data_filename = compile_matches.value_of("DATA");
 
@@ -319,8 +386,66 @@ fn linker_main() -> Status {
         prog.set_print_code(print_code);
 
         prog.set_silent(silent);
+        if quiet {
+            prog.set_verbosity(Verbosity::Quiet);
+        }
+
+        prog.set_split_boc(compile_matches.is_present("SPLIT_BOC"));
+
+        prog.set_stdout_hex(compile_matches.is_present("STDOUT_HEX"));
+
+        prog.set_merge_data(compile_matches.is_present("MERGE_DATA"));
 
-        prog.compile_to_file_ex(wc, out_file, data_filename)?;
+        let boc_format = match compile_matches.value_of("BOC_FORMAT") {
+            Some(format) => SerializationFormat::from_str(format)?,
+            None => SerializationFormat::Default,
+        };
+        prog.set_boc_format(boc_format);
+
+        if let Some(kind) = compile_matches.value_of("SELECTOR") {
+            prog.set_selector_kind(SelectorKind::from_str(kind)?);
+        }
+
+        prog.set_check_abi(compile_matches.is_present("CHECK_ABI"));
+
+        if let Some(salt) = compile_matches.value_of("CODE_SALT") {
+            prog.set_code_salt(program::load_cell_file(salt, "salt")?);
+        }
+
+        if let Some(deploy_code) = compile_matches.value_of("DEPLOY_CODE") {
+            prog.set_deploy_code(program::load_cell_file(deploy_code, "code")?);
+        }
+
+        for method in compile_matches.values_of("EXTERNAL_METHOD").unwrap_or_default() {
+            let (name, code) = method.split_once('=')
+                .ok_or_else(|| format_err!("external method {} should be given as <name_or_id>=<boc_file>", method))?;
+            let id = match name.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => name.parse::<u32>().ok(),
+            }.unwrap_or_else(|| gen_method_id(abi_contract.clone(), name, method_id_scheme.unwrap_or_default()));
+            let code = match code.strip_prefix("lib:") {
+                Some(hash) => program::library_cell(&UInt256::from_str(hash)
+                    .map_err(|e| format_err!("invalid library hash {}: {}", hash, e))?)?,
+                None => program::load_cell_file(code, "method")?,
+            };
+            prog.add_external_method(id, code);
+        }
+
+        if let Some(depth) = compile_matches.value_of("MAX_CELL_DEPTH") {
+            prog.set_max_cell_depth(depth.parse()
+                .map_err(|e| format_err!("Failed to parse max cell depth {}: {}", depth, e))?);
+        }
+
+        if compile_matches.is_present("JSON") {
+            let summary = prog.compile_to_file_json(&workchains, out_file, data_filename)?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        } else {
+            let file_name = prog.compile_to_file_for_workchains(&workchains, out_file, data_filename)?;
+            if compile_matches.is_present("SIZE") {
+                let size = program::state_size(&load_from_file(&file_name)?, boc_format)?;
+                println!("Contract size: {} bytes, {} cells, {} bits", size.boc_bytes, size.cells, size.bits);
+            }
+        }
 
         if compile_matches.is_present("DEBUG_MAP") {
             let filename = compile_matches.value_of("DEBUG_MAP").unwrap();
@@ -339,25 +464,55 @@ fn linker_main() -> Status {
         return replace_command(matches);
     }
 
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        return verify_command(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("address") {
+        return address_command(matches);
+    }
+
     unreachable!()
 }
 
-fn replace_command(matches: &ArgMatches) -> Status {
+fn address_command(matches: &ArgMatches) -> Status {
+    let wc = match matches.value_of("WC") {
+        Some(wc) => wc.parse::<i8>().map_err(|e| format_err!("invalid workchain id {}: {}", wc, e))?,
+        None => -1,
+    };
+    let state = load_from_file(matches.value_of("TVC").unwrap())?;
+    let (address, friendly) = recompute_address(&state, wc)?;
+    println!("Raw address: {}:{:x}", wc, address);
+    println!("testnet:");
+    println!("Non-bounceable address (for init): {}", friendly.testnet_non_bounceable);
+    println!("Bounceable address (for later access): {}", friendly.testnet_bounceable);
+    println!("mainnet:");
+    println!("Non-bounceable address (for init): {}", friendly.mainnet_non_bounceable);
+    println!("Bounceable address (for later access): {}", friendly.mainnet_bounceable);
+    Ok(())
+}
+
+/// Collects the sources of the contract the same way for the subcommands compiling it: the libraries
+/// given with `--lib` or `TVM_LINKER_LIB_PATH` followed by the input. The ABI is taken from `--abi-json`
+/// or from the file next to the input if it exists.
+fn collect_sources(matches: &ArgMatches, silent: bool) -> Result<(Option<String>, Vec<PathBuf>)> {
     let input = matches.value_of("INPUT").unwrap();
     let abi_from_input = format!("{}{}", input.trim_end_matches("code"), "abi.json");
-    let abi_file = matches.value_of("ABI").or_else(|| {
-        println!("ABI_PATH (obtained from INPUT): {}", abi_from_input);
-        Some(abi_from_input.as_ref())
-    });
-    let abi_json = match abi_file {
-        Some(abi_file_name) => Some(load_abi_json_string(abi_file_name)?),
+    let abi_file = match matches.value_of("ABI") {
+        Some(abi_file) => Some(abi_file),
+        None if input != STDIN_PATH && Path::new(&abi_from_input).exists() => {
+            if !silent {
+                println!("ABI_PATH (obtained from INPUT): {}", abi_from_input);
+            }
+            Some(abi_from_input.as_str())
+        }
         None => None
     };
-    let out_file = matches.value_of("OUT_FILE");
+    let abi_json = abi_file.map(load_abi_json_string).transpose()?;
 
     let mut sources = Vec::new();
     for lib in matches.values_of("LIB").unwrap_or_default() {
-        let path = Path::new(lib);
+        let path = PathBuf::from(lib);
         if !path.exists() {
             bail!("File {} doesn't exist", lib);
         }
@@ -365,19 +520,44 @@ fn replace_command(matches: &ArgMatches) -> Status {
     }
     let env_lib = env::var("TVM_LINKER_LIB_PATH").unwrap_or_default();
     if sources.is_empty() && !env_lib.is_empty() {
-        println!("TVM_LINKER_LIB_PATH: {:?}", &env_lib);
-        let path = Path::new(&env_lib);
+        if !silent {
+            println!("TVM_LINKER_LIB_PATH: {:?}", &env_lib);
+        }
+        let path = PathBuf::from(&env_lib);
         if !path.exists() {
             bail!("File {} doesn't exist", &env_lib);
         }
         sources.push(path);
     }
 
-    let path = Path::new(input);
-    if !path.exists() {
+    let path = PathBuf::from(input);
+    if input != STDIN_PATH && !path.exists() {
         bail!("File {} doesn't exist", input);
     }
-    sources.push(path.clone());
+    sources.push(path);
+    Ok((abi_json, sources))
+}
+
+fn verify_command(matches: &ArgMatches) -> Status {
+    let (abi_json, sources) = collect_sources(matches, false)?;
+    let sources = sources.iter().map(|path| path.as_path()).collect();
+
+    let tvc = matches.value_of("TVC").unwrap();
+    if let Some(path) = verify_against(sources, abi_json, tvc)? {
+        println!("Code differs at cell {}", path);
+        bail!("Code of {} doesn't match the source", tvc);
+    }
+    println!("Code of {} matches the source", tvc);
+    Ok(())
+}
+
+fn replace_command(matches: &ArgMatches) -> Status {
+    let input = matches.value_of("INPUT").unwrap();
+    let (abi_json, sources) = collect_sources(matches, false)?;
+    let out_file = matches.value_of("OUT_FILE");
+
+    let path = Path::new(input);
+    let sources = sources.iter().map(|path| path.as_path()).collect();
 
     let mut prog_opt = None;
     let code = match ParseEngine::new(sources, abi_json) {
@@ -458,16 +638,20 @@ fn decode_hex_string(hex_str: String) -> Result<(Vec<u8>, usize)> {
     }
 }
 
-fn decode_boc(filename: &str, is_tvc: bool) -> Status {
-    let (mut root_slice, orig_bytes) = program::load_stateinit(filename)?;
-
-    println!("Encoded: {}\n", hex::encode(orig_bytes));
+fn decode_boc(filename: &str, is_tvc: bool, tlb: bool, options: &printer::MsgPrinterOptions) -> Status {
     if is_tvc {
+        let (mut root_slice, orig_bytes) = program::load_stateinit(filename)?;
+        println!("Encoded: {}\n", hex::encode(orig_bytes));
         let state = StateInit::construct_from(&mut root_slice)?;
-        println!("Decoded:\n{}", printer::state_init_printer(&state));
+        if tlb {
+            println!("Decoded:\n{}", printer::state_init_tlb_dump(&state));
+        } else {
+            println!("Decoded:\n{}", printer::state_init_printer(&state));
+        }
     } else {
-        let msg = Message::construct_from(&mut root_slice)?;
-        println!("Decoded:\n{}", printer::msg_printer(&msg)?);
+        let msg = program::load_message(filename)?;
+        println!("Encoded: {}\n", hex::encode(std::fs::read(filename)?));
+        println!("Decoded:\n{}", printer::msg_printer_ex(&msg, options)?);
     }
     Ok(())
 }
@@ -480,6 +664,7 @@ fn run_test_subcmd(matches: &ArgMatches) -> Status {
         "0".repeat(64)
     };
     let address = matches.value_of("ADDRESS").unwrap_or(&addr_from_input);
+    let now = parse_now(matches.value_of("NOW"))?;
     let (body, sign) = match matches.value_of("BODY") {
         Some(hex_str) => {
             let parse_results = match matches.value_of("SOURCE") {
@@ -509,11 +694,10 @@ fn run_test_subcmd(matches: &ArgMatches) -> Status {
             let body = SliceData::from_raw(buf, buf_bits);
             (Some(body), Some(matches.value_of("SIGN")))
         },
-        None => (build_body(matches, Some(address.to_string()))?, None),
+        None => (build_body(matches, Some(address.to_string()), now)?, None),
     };
 
     let ticktock = parse_ticktock(matches.value_of("TICKTOCK"))?;
-    let now = parse_now(matches.value_of("NOW"))?;
 
     let action_decoder = |body, is_internal| {
         let abi_file = matches.value_of("ABI_JSON");
@@ -565,17 +749,20 @@ fn run_test_subcmd(matches: &ArgMatches) -> Status {
     };
 
     if let Some(filename) = matches.value_of("BODY_FROM_BOC") {
-        let (mut root_slice, _) = program::load_stateinit(filename)?;
-        let msg = Message::construct_from(&mut root_slice)?;
-        msg_info.body = msg.body();
+        msg_info.body = program::load_message(filename)?.body();
     }
 
-    let gas_limit = matches.value_of("GASLIMIT")
-        .map(|v| v.parse::<i64>())
-        .transpose()?;
+    let gas_limit = match matches.value_of("GASCREDIT") {
+        Some(v) => Some(GasLimit::Credit(v.parse::<i64>()?)),
+        None => matches.value_of("GASLIMIT")
+            .map(|v| v.parse::<i64>().map(GasLimit::Limit))
+            .transpose()?,
+    };
 
     let mut trace_level = TraceLevel::None;
-    if matches.is_present("TRACE") {
+    if matches.is_present("TRACE_STACK_DIFF") {
+        trace_level = TraceLevel::StackDiff;
+    } else if matches.is_present("TRACE") {
         trace_level = TraceLevel::Full;
     } else if matches.is_present("TRACE_MIN") {
         trace_level = TraceLevel::Minimal;
@@ -591,18 +778,21 @@ fn run_test_subcmd(matches: &ArgMatches) -> Status {
     let state_init = load_from_file(&input)?;
     let config_cell_opt = matches.value_of("CONFIG").and_then(testcall::load_config);
 
-    let capabilities =
-        match config_cell_opt {
-            Some(ref config_cell) => {
-                let config_params = ConfigParams::with_address_and_root(
-                    UInt256::from_str(&"5".repeat(64)).unwrap(), // -1:5555...
-                    config_cell.clone());
-                config_params.capabilities()
-            }
-            None => {
-                DEFAULT_CAPABILITIES
-            }
-        };
+    let capabilities = match matches.value_of("CAPABILITIES") {
+        Some(caps) => Capabilities::from_str(caps)?.mask(),
+        None => config_capabilities(config_cell_opt.as_ref()),
+    };
+    let debug_info = testcall::load_debug_info(&debug_map_filename.unwrap_or("".to_string()));
+    let trace_filter = match matches.value_of("TRACE_METHOD") {
+        Some(name) => {
+            let debug_info = debug_info.as_ref()
+                .ok_or_else(|| format_err!("--trace-method requires a debug map"))?;
+            let source = Path::new(matches.value_of("SOURCE").unwrap());
+            let symbols = ParseEngineResults::new(ParseEngine::new(vec![source], None)?).symbols();
+            Some(testcall::method_cells(debug_info, &symbols, name)?)
+        }
+        None => None
+    };
     let (_, state_init, is_success) = call_contract(addr, state_init, TestCallParams {
         balance: matches.value_of("BALANCE"),
         msg_info,
@@ -612,8 +802,21 @@ fn run_test_subcmd(matches: &ArgMatches) -> Status {
         gas_limit,
         action_decoder: if matches.is_present("DECODEC6") { Some(action_decoder) } else { None },
         trace_level,
-        debug_info: testcall::load_debug_info(&debug_map_filename.unwrap_or("".to_string())),
-        capabilities
+        debug_info,
+        trace_callback: if matches.is_present("TRACE_REGISTERS") {
+            Some(Box::new(testcall::trace_registers))
+        } else {
+            None
+        },
+        capabilities,
+        vm_version: matches.value_of("VM_VERSION").map(VmVersion::from_str).transpose()?,
+        block_time_override: matches.value_of("BLOCK_TIME")
+            .map(|v| v.parse::<u32>().map_err(|e| format_err!("failed to parse \"block-time\" option: {}", e)))
+            .transpose()?,
+        trace_filter,
+        global_id: matches.value_of("GLOBAL_ID")
+            .map(|v| v.parse::<i32>().map_err(|e| format_err!("failed to parse \"global-id\" option: {}", e)))
+            .transpose()?,
     })?;
     if is_success {
         save_to_file(state_init, Some(&input), 0, false)?;
@@ -624,13 +827,53 @@ fn run_test_subcmd(matches: &ArgMatches) -> Status {
     Ok(())
 }
 
-fn build_body(matches: &ArgMatches, address: Option<String>) -> Result<Option<SliceData>> {
+fn run_replay_subcmd(matches: &ArgMatches) -> Status {
+    let mut trace_level = TraceLevel::None;
+    if matches.is_present("TRACE_STACK_DIFF") {
+        trace_level = TraceLevel::StackDiff;
+    } else if matches.is_present("TRACE") {
+        trace_level = TraceLevel::Full;
+    } else if matches.is_present("TRACE_MIN") {
+        trace_level = TraceLevel::Minimal;
+    }
+    let account_file = matches.value_of("ACCOUNT").unwrap();
+    println!("REPLAY STARTED");
+    let (_, state_init, is_success) = replay_transaction(
+        account_file,
+        matches.value_of("MESSAGE").unwrap(),
+        matches.value_of("CONFIG"),
+        trace_level,
+    )?;
+    if let Some(out_file) = matches.value_of("OUT_FILE") {
+        if is_success {
+            let mut account = Account::construct_from_file(account_file)?;
+            update_account_state(&mut account, &state_init)?;
+            save_account_to_file(&account, out_file)?;
+            println!("Account state saved to file: {}", out_file);
+        } else {
+            println!("Account state is not saved: compute phase failed");
+        }
+    }
+    println!("REPLAY COMPLETED");
+    Ok(())
+}
+
+fn build_body(matches: &ArgMatches, address: Option<String>, now: u32) -> Result<Option<SliceData>> {
     let mut mask = 0u8;
     let abi_file = matches.value_of("ABI_JSON").map(|m| { mask |= 1; m });
     let method_name = matches.value_of("ABI_METHOD").map(|m| { mask |= 2; m });
     let params = matches.value_of("ABI_PARAMS");
     let header = matches.value_of("ABI_HEADER");
     if mask == 0x3 {
+        let header = if matches.is_present("INTERNAL") {
+            header.map(|v| v.to_owned())
+        } else {
+            let ttl = match matches.value_of("EXPIRE_TTL") {
+                Some(ttl) => ttl.parse::<u32>().map_err(|e| format_err!("failed to parse \"expire-ttl\" option: {}", e))?,
+                None => DEFAULT_EXPIRE_TTL,
+            };
+            header_with_expire(&load_abi_json_string(abi_file.unwrap())?, header, now, ttl)?
+        };
         let key_file = match matches.value_of("SIGN") {
             Some(path) => {
                 let pair = KeypairManager::from_file(path)?;
@@ -651,7 +894,7 @@ fn build_body(matches: &ArgMatches, address: Option<String>) -> Result<Option<Sl
             abi_file.unwrap(),
             method_name.unwrap(),
             &params,
-            header,
+            header.as_deref(),
             key_file,
             is_internal,
             address,
@@ -12,11 +12,44 @@
  */
 use std::collections::{BTreeMap, HashMap};
 use ton_block::Serializable;
-use ton_labs_assembler::{Lines, DbgInfo};
+use ton_labs_assembler::{Line, Lines, DbgInfo};
 use ton_types::{SliceData, dictionary::HashmapE};
 
 use crate::program::Program;
 
+/// Exception thrown by the debug guards when a method leaves an unexpected number of items on the stack.
+pub const STACK_GUARD_EXCEPTION: u16 = 2000;
+
+/// Wraps the method body with a check of the stack balance: the depth of the stack on entry is saved
+/// under the arguments of the method, the body is called as a continuation, then the depth on exit
+/// is compared with the saved one plus `delta`, the number of results minus the number of arguments.
+pub fn guard_method(body: &Lines, delta: i32) -> Lines {
+    let line = |text: &str, n: usize| Line::new(&format!("{}\n", text), "<debug-guard>", n);
+    let mut lines = vec![
+        line("DEPTH", 1),
+        line("DUP", 2),
+        line("ROLLREVX", 3),
+        line("PUSHCONT {", 4),
+    ];
+    lines.extend(body.iter().cloned());
+    lines.extend([
+        line("}", 5),
+        line("CALLX", 6),
+        line("DEPTH", 7),
+        line("DEC", 8),
+        line("ROLLX", 9),
+        line("DEPTH", 10),
+        line("DEC", 11),
+        line("SUB", 12),
+    ]);
+    if delta != 0 {
+        lines.push(line(&format!("PUSHINT {}", delta), 13));
+        lines.push(line("ADD", 14));
+    }
+    lines.push(line(&format!("THROWIF {}", STACK_GUARD_EXCEPTION), 15));
+    lines
+}
+
 impl Program {
     pub fn prepare_methods<T>(
         &mut self,
@@ -46,7 +79,13 @@ impl Program {
         for pair in methods.iter() {
             let key: SliceData = SliceData::load_cell(pair.0.clone().serialize()
                 .map_err(|e| (pair.0.clone(), format!("Failed to serialize data: {}", e)))?).unwrap();
-            let mut val = self.assemble(pair.1.clone()).map_err(|e| {
+            let id = key.clone().get_next_i32()
+                .map_err(|e| (pair.0.clone(), format!("Failed to decode data: {}", e)))?;
+            let body = match self.stack_delta(id as u32) {
+                Some(delta) if self.debug_guards() => guard_method(pair.1, delta),
+                _ => pair.1.clone(),
+            };
+            let mut val = self.assemble(body).map_err(|e| {
                 (pair.0.clone(), e.to_string())
             })?;
             if val.0.remaining_bits() <= (1023 - (32 + 10)) { // key_length + hashmap overheads
@@ -58,8 +97,6 @@ impl Program {
                     (pair.0.clone(), format!("failed to set method _name_ to dictionary: {}", e))
                 })?;
             }
-            let id = key.clone().get_next_i32()
-                .map_err(|e| (pair.0.clone(), format!("Failed to decode data: {}", e)))?;
             if adjust_entry_points || id < -2 || id > 0 {
                 let before = val.0;
                 let after = map.get(key)
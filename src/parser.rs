@@ -11,7 +11,8 @@
  * limitations under the License.
  */
 
-use crate::abi::{gen_abi_id, load_abi_contract};
+use crate::abi::{gen_abi_id, gen_method_id, load_abi_contract, MethodIdScheme};
+use crate::error::LinkerError;
 use crate::resolver::resolve_name;
 
 use ton_types::{BuilderData, IBitstring, SliceData, Cell, Result, Status};
@@ -25,9 +26,9 @@ use failure::{format_err, bail};
 use regex::Regex;
 
 use std::collections::{BTreeMap, HashSet, HashMap};
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, Cursor, Read};
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub type Ptr = i64;
 
@@ -71,6 +72,10 @@ impl ParseEngineResults {
     pub fn version(&self) -> Option<String> {
         self.engine.version()
     }
+    /// Parses the sources again with the given scheme of public function ids.
+    pub fn with_method_id_scheme(&self, method_id_scheme: MethodIdScheme) -> Result<Self> {
+        Ok(ParseEngineResults::new(self.engine.with_method_id_scheme(method_id_scheme)?))
+    }
     pub fn func_upgrade(&self) -> SelectorVariant {
         self.engine.func_upgrade()
     }
@@ -80,8 +85,36 @@ impl ParseEngineResults {
     pub fn postorder_fragments(&self) -> &Vec<String> {
         &self.engine.postorder_fragments
     }
+    pub fn symbols(&self) -> Symbols {
+        self.engine.symbols()
+    }
+    pub fn unused_privates(&self) -> Vec<String> {
+        self.engine.unused_privates()
+    }
+    pub fn abi_mismatches(&self) -> (Vec<String>, Vec<String>) {
+        self.engine.abi_mismatches()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Public,
+    Private,
+    Internal,
+}
+
+/// Function defined in the assembly: `.globl` (public or private) or `.internal`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub id: i64,
+    /// position of the declaring `.type` or `.internal` directive
+    pub pos: Option<DbgPos>,
 }
 
+pub type Symbols = Vec<Symbol>;
+
 pub fn ptr_to_builder(n: Ptr) -> Result<BuilderData> {
     let mut b = BuilderData::new();
     b.append_i64(n).map_err(|e| format_err!("failed to serialize an i64 to builder: {}", e))?;
@@ -94,11 +127,12 @@ struct InternalFunc {
     pub body: Lines,
     // function ids that this function calls from its body
     pub called_ids: Vec<u32>,
+    pub pos: Option<DbgPos>,
 }
 
 impl InternalFunc {
     pub fn new() -> Self {
-        InternalFunc { id: 0, body: vec![], called_ids: vec![] }
+        InternalFunc { id: 0, body: vec![], called_ids: vec![], pos: None }
     }
 }
 
@@ -122,7 +156,7 @@ enum FunctionId {
 impl From<&str> for GloblFuncOrDataType {
     fn from(stype: &str) -> GloblFuncOrDataType {
         match stype {
-            "function" => GloblFuncOrDataType::Function(InternalFunc { id: 0, body: vec![], called_ids: vec![] }),
+            "function" => GloblFuncOrDataType::Function(InternalFunc::new()),
             "object" => GloblFuncOrDataType::Data(Data { addr: 0, values: vec![], persistent: false }),
             _ => GloblFuncOrDataType::None,
         }
@@ -267,6 +301,13 @@ pub struct ParseEngine {
     func_upgrade: SelectorVariant,
     ///
     save_all_private_functions: bool,
+    /// Id calculation for public functions missing in ABI
+    method_id_scheme: MethodIdScheme,
+    /// Id calculation set by the caller, it takes precedence over the pragma
+    method_id_scheme_override: Option<MethodIdScheme>,
+    /// Sources and ABI kept to parse them again, e.g. with another id calculation
+    sources: Vec<(String, Vec<u8>)>,
+    abi_json: Option<String>,
     /// Contract version
     version: Option<String>,
 
@@ -297,6 +338,7 @@ lazy_static! {
     static ref LOC_REGEX: Regex = Regex::new(r"^\s*\.loc\s+(.+),\s+(\d+)\n$").unwrap();
     static ref VERSION_REGEX: Regex = Regex::new(r"^\s*\.version\s+(.+)").unwrap();
     static ref PRAGMA_REGEX: Regex = Regex::new(r"^\s*\.pragma\s+(.+)").unwrap();
+    static ref INCLUDE_REGEX: Regex = Regex::new(r#"^\s*\.include\s+"(.+)""#).unwrap();
 
     static ref COMPUTE_REGEX: Regex = Regex::new(r"^\s*\.compute\s+\$([\w\.:]+)\$").unwrap();
     static ref CALL_REGEX: Regex = Regex::new(r"^\s*CALL\s+\$([\w\.:]+)\$").unwrap();
@@ -312,9 +354,20 @@ const DATA_TYPENAME:    &str = "object";
 
 const PERSISTENT_DATA_SUFFIX: &str = "_persistent";
 
+pub const STDIN_PATH:   &str = "-";
+const STDIN_NAME:       &str = "<stdin>";
+
 const PUBKEY_NAME:      &str = "tvm_public_key";
 const SCI_NAME:         &str = "tvm_contract_info";
 
+fn parse_error(filename: &str, lnum: usize, message: String) -> failure::Error {
+    LinkerError::ParseError { file: filename.to_string(), line: lnum, message }.into()
+}
+
+fn syntax_error(filename: &str, lnum: usize, line: &str) -> failure::Error {
+    parse_error(filename, lnum, format!("invalid syntax: {}", line.trim()))
+}
+
 fn starts_with(sample: &str, pattern: &str) -> bool {
     sample.trim_start_matches(char::is_whitespace).starts_with(pattern)
 }
@@ -326,18 +379,102 @@ pub struct ParseEngineInput<'a> {
 
 impl ParseEngine {
 
+    /// Parses assembler sources from files, `-` path stands for the standard input.
+    /// Files referenced by `.include "path"` directives are parsed before the including file,
+    /// the path is resolved relative to the including file.
     pub fn new(sources: Vec<&Path>, abi_json: Option<String>) -> Result<Self> {
         let mut inputs = vec!();
+        let mut included = HashSet::new();
         for path in sources {
-            let name = path.file_name().unwrap().to_str().unwrap().to_string();
-            let file = File::open(path)
-                .map_err(|e| format_err!("Failed to open file {}: {}", path.to_str().unwrap(), e))?;
-            inputs.push(ParseEngineInput { buf: Box::new(file), name });
+            if path == Path::new(STDIN_PATH) {
+                let mut text = String::new();
+                std::io::stdin().read_to_string(&mut text)
+                    .map_err(|e| format_err!("Failed to read file {}: {}", STDIN_NAME, e))?;
+                Self::push_includes(&text, STDIN_NAME, Path::new(""), &mut vec![], &mut included, &mut inputs)?;
+                inputs.push(ParseEngineInput { buf: Box::new(Cursor::new(text)), name: STDIN_NAME.to_string() });
+                continue
+            }
+            Self::push_source(path, None, &mut vec![], &mut included, &mut inputs)?;
         }
         Self::new_generic(inputs, abi_json)
     }
 
+    /// `origin` is the file name and the line of the `.include` directive referencing the path, if any.
+    fn push_source(
+        path: &Path,
+        origin: Option<(&str, usize)>,
+        stack: &mut Vec<PathBuf>,
+        included: &mut HashSet<PathBuf>,
+        inputs: &mut Vec<ParseEngineInput>,
+    ) -> Status {
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if stack.contains(&key) {
+            let message = format!("Include cycle detected: {}", path.to_str().unwrap());
+            return Err(match origin {
+                Some((filename, lnum)) => parse_error(filename, lnum, message),
+                None => format_err!("{}", message),
+            })
+        }
+        if !included.insert(key.clone()) {
+            return Ok(())
+        }
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                stack.push(key);
+                Self::push_includes(&text, &name, path.parent().unwrap_or_else(|| Path::new("")), stack, included, inputs)?;
+                stack.pop();
+                inputs.push(ParseEngineInput { buf: Box::new(Cursor::new(text)), name });
+            }
+            Err(_) => {
+                // let the parser report reading errors
+                let file = File::open(path).map_err(|e| {
+                    let message = format!("Failed to open file {}: {}", path.to_str().unwrap(), e);
+                    match origin {
+                        Some((filename, lnum)) => parse_error(filename, lnum, message),
+                        None => format_err!("{}", message),
+                    }
+                })?;
+                inputs.push(ParseEngineInput { buf: Box::new(file), name });
+            }
+        }
+        Ok(())
+    }
+
+    fn push_includes(
+        text: &str,
+        name: &str,
+        dir: &Path,
+        stack: &mut Vec<PathBuf>,
+        included: &mut HashSet<PathBuf>,
+        inputs: &mut Vec<ParseEngineInput>,
+    ) -> Status {
+        for (lnum, line) in text.lines().enumerate() {
+            if let Some(cap) = INCLUDE_REGEX.captures(line) {
+                Self::push_source(&dir.join(cap.get(1).unwrap().as_str()), Some((name, lnum + 1)), stack, included, inputs)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn new_generic(inputs: Vec<ParseEngineInput>, abi_json: Option<String>) -> Result<Self> {
+        Self::new_generic_ex(inputs, abi_json, None)
+    }
+
+    /// Same as `new_generic`, but ids of public functions missing in the ABI are calculated with
+    /// the given scheme regardless of `.pragma method-id-crc16`.
+    pub fn new_generic_ex(
+        inputs: Vec<ParseEngineInput>,
+        abi_json: Option<String>,
+        method_id_scheme: Option<MethodIdScheme>,
+    ) -> Result<Self> {
+        let mut sources = Vec::new();
+        for mut input in inputs {
+            let mut bytes = Vec::new();
+            input.buf.read_to_end(&mut bytes)
+                .map_err(|e| format_err!("Failed to read file {}: {}", input.name, e))?;
+            sources.push((input.name, bytes));
+        }
         let mut engine = ParseEngine {
             globl_name_to_id: HashMap::new(),
             internal_name_to_id: HashMap::new(),
@@ -355,12 +492,28 @@ impl ParseEngine {
             version: None,
             func_upgrade: SelectorVariant::Default,
             computed: HashMap::new(),
-            save_all_private_functions: false
+            save_all_private_functions: false,
+            method_id_scheme: MethodIdScheme::default(),
+            method_id_scheme_override: method_id_scheme,
+            sources: Vec::new(),
+            abi_json: abi_json.clone(),
         };
+        let inputs = sources.iter()
+            .map(|(name, bytes)| ParseEngineInput { buf: Box::new(bytes.as_slice()), name: name.clone() })
+            .collect();
         engine.parse(inputs, abi_json)?;
+        engine.sources = sources;
         Ok(engine)
     }
 
+    /// Parses the same sources again with the given scheme of public function ids.
+    pub fn with_method_id_scheme(&self, method_id_scheme: MethodIdScheme) -> Result<Self> {
+        let inputs = self.sources.iter()
+            .map(|(name, bytes)| ParseEngineInput { buf: Box::new(bytes.as_slice()), name: name.clone() })
+            .collect();
+        Self::new_generic_ex(inputs, self.abi_json.clone(), Some(method_id_scheme))
+    }
+
     fn parse(&mut self, inputs: Vec<ParseEngineInput>, abi_json: Option<String>) -> Status {
         if let Some(s) = abi_json {
             self.abi = Some(load_abi_contract(&s)?);
@@ -438,6 +591,45 @@ impl ParseEngine {
         })
     }
 
+    /// Names of ABI functions missing in the sources and names of public functions missing in the ABI.
+    /// Both lists are empty if no ABI is given.
+    pub fn abi_mismatches(&self) -> (Vec<String>, Vec<String>) {
+        let abi = match self.abi {
+            Some(ref abi) => abi,
+            None => return (vec![], vec![]),
+        };
+        let mut not_implemented = abi.functions().keys()
+            .filter(|name| self.globl_name_to_object.get(*name)
+                .map(|obj| !obj.public || obj.dtype.func().is_none())
+                .unwrap_or(true))
+            .cloned()
+            .collect::<Vec<_>>();
+        not_implemented.sort();
+        let not_in_abi = self.globl_name_to_object.iter()
+            .filter(|(name, obj)| obj.public && obj.dtype.func().is_some() && !abi.functions().contains_key(*name))
+            .map(|(name, _obj)| name.clone())
+            .collect();
+        (not_implemented, not_in_abi)
+    }
+
+    fn symbols(&self) -> Symbols {
+        let globals = self.globl_name_to_object.iter().filter_map(|(name, global)| {
+            global.dtype.func().map(|func| Symbol {
+                name: name.clone(),
+                kind: if global.public { SymbolKind::Public } else { SymbolKind::Private },
+                id: func.id as i64,
+                pos: func.pos.clone(),
+            })
+        });
+        let internals = self.internal_id_to_code.iter().map(|(&id, func)| Symbol {
+            name: self.internal_name(id).unwrap_or_default(),
+            kind: SymbolKind::Internal,
+            id: id as i64,
+            pos: func.pos.clone(),
+        });
+        globals.chain(internals).collect()
+    }
+
     fn preinit(&mut self) -> Status {
         // TODO delete
         self.globl_name_to_object.insert(
@@ -499,6 +691,7 @@ impl ParseEngine {
         let mut section_name = String::new();
         let mut obj_body = vec![];
         let mut obj_name = String::new();
+        let mut obj_pos = None;
         let mut lnum = 0;
         let mut l = String::new();
         let mut source_pos: Option<DbgPos> = None;
@@ -528,14 +721,15 @@ impl ParseEngine {
                starts_with(&l, ".text") ||
                starts_with(&l, ".file") ||
                starts_with(&l, ".ident") ||
-               starts_with(&l, ".section") {
+               starts_with(&l, ".section") ||
+               starts_with(&l, ".include") {
                 //ignore unused parameters
                 debug!("ignored: {}", l);
             } else if starts_with(&l, ".version") {
-                let cap = VERSION_REGEX.captures(&l).unwrap();
+                let cap = VERSION_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 self.version = Some(cap.get(1).unwrap().as_str().to_owned());
             } else if starts_with(&l, ".pragma") {
-                let cap = PRAGMA_REGEX.captures(&l).unwrap();
+                let cap = PRAGMA_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 if let Some(m) = cap.get(1) {
                     if m.as_str() == "selector-func-solidity" {
                         self.func_upgrade = SelectorVariant::UpdateFunc;
@@ -543,93 +737,98 @@ impl ParseEngine {
                         self.func_upgrade = SelectorVariant::UpdateOldSol;
                     } else if m.as_str() == "save-all-private-functions" {
                         self.save_all_private_functions = true
+                    } else if m.as_str() == "method-id-crc16" {
+                        self.method_id_scheme = MethodIdScheme::Crc16
                     } else {
-                        bail!(format!("Unknown pragma: {}", m.as_str()));
+                        return Err(parse_error(&filename, lnum, format!("Unknown pragma: {}", m.as_str())));
                     }
                 }
             } else if starts_with(&l, ".global-base") {
                 // .global-base
-                let cap = BASE_GLBL_REGEX.captures(&l).unwrap();
+                let cap = BASE_GLBL_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 let base = cap.get(1).map(|m| m.as_str())
-                    .ok_or_else(|| format_err!("line {}: invalid syntax for global base", lnum))?;
+                    .ok_or_else(|| parse_error(&filename, lnum, "invalid syntax for global base".to_string()))?;
                 self.globl_base = Ptr::from_str_radix(base, 10)
-                    .map_err(|_| format_err!("line {}: invalid global base address", lnum))?;
+                    .map_err(|_| parse_error(&filename, lnum, "invalid global base address".to_string()))?;
                 self.globl_ptr = self.globl_base + OFFSET_GLOBL_DATA;
                 self.update_predefined();
             } else if starts_with(&l, ".persistent-base") {
                 // .persistent-base
-                let cap = BASE_PERS_REGEX.captures(&l).unwrap();
+                let cap = BASE_PERS_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 let base = cap.get(1).map(|m| m.as_str())
-                    .ok_or_else(|| format_err!("line {}: invalid syntax for persistent base", lnum))?;
+                    .ok_or_else(|| parse_error(&filename, lnum, "invalid syntax for persistent base".to_string()))?;
                 self.persistent_base = Ptr::from_str_radix(base, 10)
-                    .map_err(|_| format_err!("line {}: invalid persistent base address", lnum))?;
+                    .map_err(|_| parse_error(&filename, lnum, "invalid persistent base address".to_string()))?;
                 self.persistent_ptr = self.persistent_base + OFFSET_PERS_DATA;
                 self.update_predefined();
             } else if starts_with(&l, ".type") {
                 // .type x, @...
                 //it's a mark for beginning of a new object (func or data)
-                self.update(&section_name, &obj_name, &obj_body)
-                    .map_err(|e| format_err!("line {}: {}", lnum, e))?;
+                self.update(&section_name, &obj_name, &obj_body, &obj_pos)
+                    .map_err(|e| parse_error(&filename, lnum, e.to_string()))?;
                 section_name = GLOBL.to_owned();
                 obj_body = vec![];
-                let cap = TYPE_REGEX.captures(&l).unwrap();
+                obj_pos = Some(DbgPos { filename: filename.clone(), line: lnum, line_code: lnum });
+                let cap = TYPE_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 obj_name = cap.get(1).unwrap().as_str().to_owned();
-                let type_name = cap.get(2).ok_or_else(|| format_err!("line {}: .type option is invalid", lnum))?.as_str();
+                let type_name = cap.get(2).ok_or_else(|| parse_error(&filename, lnum, ".type option is invalid".to_string()))?.as_str();
                 let obj = self.globl_name_to_object.entry(obj_name.clone()).or_insert_with(|| GloblFuncOrData::new(obj_name.clone(), type_name));
                 obj.dtype = GloblFuncOrDataType::from(type_name);
             } else if starts_with(&l, ".size") {
                 // .size x, val
-                let cap = SIZE_REGEX.captures(&l).unwrap();
+                let cap = SIZE_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 let name = cap.get(1).unwrap().as_str().to_owned();
-                let size_str = cap.get(2).ok_or_else(|| format_err!("line {}: .size option is invalid", lnum))?.as_str();
+                let size_str = cap.get(2).ok_or_else(|| parse_error(&filename, lnum, ".size option is invalid".to_string()))?.as_str();
                 let item_ref = self.globl_name_to_object.entry(name.clone()).or_insert_with(|| GloblFuncOrData::new(name, ""));
                 item_ref.size = size_str.parse::<usize>().unwrap_or(0);
             } else if starts_with(&l, ".public") {
                 // .public x
-                let cap = PUBLIC_REGEX.captures(&l).unwrap();
+                let cap = PUBLIC_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 let name = cap.get(1).unwrap().as_str();
                 self.globl_name_to_object.get_mut(name).map(|obj| { obj.public = true; Some(obj) });
             } else if starts_with(&l, ".globl") {
                 // .globl x
-                let cap = GLOBL_REGEX.captures(&l).unwrap();
+                let cap = GLOBL_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 let name = cap.get(1).unwrap().as_str().to_owned();
                 self.globl_name_to_object.entry(name.clone()).or_insert_with(|| GloblFuncOrData::new(name.clone(), ""));
             } else if starts_with(&l, ".macro") {
                 // .macro x
-                self.update(&section_name, &obj_name, &obj_body)
-                    .map_err(|e| format_err!("line {}: {}", lnum, e))?;
+                self.update(&section_name, &obj_name, &obj_body, &obj_pos)
+                    .map_err(|e| parse_error(&filename, lnum, e.to_string()))?;
                 section_name = MACROS.to_owned();
                 obj_body = vec![];
-                obj_name = MACRO_REGEX.captures(&l).unwrap().get(1).unwrap().as_str().to_owned();
+                obj_name = MACRO_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?.get(1).unwrap().as_str().to_owned();
             } else if starts_with(&l, ".data") {
                 // .data
                 //ignore, not used
             } else if starts_with(&l, ".selector") {
                 // .selector
-                self.update(&section_name, &obj_name, &obj_body)?;
+                self.update(&section_name, &obj_name, &obj_body, &obj_pos)
+                    .map_err(|e| parse_error(&filename, lnum, e.to_string()))?;
                 section_name = SELECTOR.to_owned();
                 obj_name = "".to_owned();
                 obj_body = vec![];
             } else if starts_with(&l, ".internal-alias") {
                 // .internal-alias
-                let cap = ALIAS_REGEX.captures(&l).unwrap();
+                let cap = ALIAS_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 self.internal_alias_name_to_id_.insert(
                     cap.get(1).unwrap().as_str().to_owned(),
                     cap.get(2).unwrap().as_str().parse::<i32>()
-                        .map_err(|_| format_err!("line: '{}': failed to parse id", lnum))?,
+                        .map_err(|_| parse_error(&filename, lnum, "failed to parse id".to_string()))?,
                 );
             } else if starts_with(&l, ".internal") {
                 // .internal
-                self.update(&section_name, &obj_name, &obj_body)
-                    .map_err(|e| format_err!("line {}: {}", lnum, e))?;
+                self.update(&section_name, &obj_name, &obj_body, &obj_pos)
+                    .map_err(|e| parse_error(&filename, lnum, e.to_string()))?;
                 section_name = INTERNAL.to_owned();
                 obj_body = vec![];
-                obj_name = INTERNAL_REGEX.captures(&l).unwrap().get(1).unwrap().as_str().to_owned();
+                obj_pos = Some(DbgPos { filename: filename.clone(), line: lnum, line_code: lnum });
+                obj_name = INTERNAL_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?.get(1).unwrap().as_str().to_owned();
             } else if LABEL_REGEX.is_match(&l) {
                 // TODO
                 // ignore labels
             } else if starts_with(&l, ".loc") {
-                let cap = LOC_REGEX.captures(&l).unwrap();
+                let cap = LOC_REGEX.captures(&l).ok_or_else(|| syntax_error(&filename, lnum, &l))?;
                 let filename = String::from(cap.get(1).unwrap().as_str());
                 let line = cap.get(2).unwrap().as_str().parse::<usize>().unwrap();
                 if line == 0 { // special value for resetting current source pos
@@ -657,11 +856,11 @@ impl ParseEngine {
         }
 
         if section_name.is_empty() {
-            bail!("input file has no assembler definitions");
+            bail!("{}: input file has no assembler definitions", filename);
         }
 
-        self.update(&section_name, &obj_name, &obj_body)
-            .map_err(|e| format_err!("line {}: {}", lnum, e))?;
+        self.update(&section_name, &obj_name, &obj_body, &obj_pos)
+            .map_err(|e| parse_error(&filename, lnum, e.to_string()))?;
         Ok(())
     }
 
@@ -747,7 +946,7 @@ impl ParseEngine {
 
             let resolved =
                 self.replace_labels(&line, &obj_name)
-                    .map_err(|e| format_err!("line {}: cannot resolve label: {}", line.pos.line, e))?;
+                    .map_err(|e| parse_error(&line.pos.filename, line.pos.line, format!("cannot resolve label: {}", e)))?;
             new_lines.push(resolved);
         }
         Ok(new_lines)
@@ -788,7 +987,11 @@ impl ParseEngine {
 
     fn create_function_id(&mut self, func: &str) -> u32 {
         let is_public = self.globl_name_to_object.get(func).unwrap().public;
-        gen_abi_id(if is_public { self.abi.clone() } else { None }, func)
+        if is_public {
+            gen_method_id(self.abi.clone(), func, self.method_id_scheme_override.unwrap_or(self.method_id_scheme))
+        } else {
+            gen_abi_id(None, func)
+        }
     }
 
     fn is_public(&self, globl_name: &str) -> bool {
@@ -800,7 +1003,7 @@ impl ParseEngine {
             .unwrap_or(false)
     }
 
-    fn update(&mut self, section: &str, name: &str, body: &Lines) -> Status {
+    fn update(&mut self, section: &str, name: &str, body: &Lines, pos: &Option<DbgPos>) -> Status {
         match section {
             SELECTOR => {
                 if self.entry_point.is_empty() {
@@ -823,6 +1026,7 @@ impl ParseEngine {
                     let params = item.dtype.func_mut().unwrap();
                     params.id = func_id;
                     params.body = body.clone();
+                    params.pos = pos.clone();
                     let prev = self.globl_name_to_id.insert(name.to_string(), func_id);
                     if prev.is_some() {
                         bail!(
@@ -854,7 +1058,9 @@ impl ParseEngine {
                 if prev.is_some() {
                     bail!("internal function with id = {} already exist", *func_id);
                 }
-                self.internal_id_to_code.get_mut(func_id).unwrap().body = body.clone();
+                let func = self.internal_id_to_code.get_mut(func_id).unwrap();
+                func.body = body.clone();
+                func.pos = pos.clone();
             },
             MACROS => {
                 let prev = self.macro_name_to_lines.insert(name.to_string(), body.clone());
@@ -1094,6 +1300,29 @@ impl ParseEngine {
     }
 
     fn drop_unused_objects(&mut self) {
+        let ids = self.reachable_ids();
+
+        self.globl_name_to_object.retain(|_k, v| {
+            v.dtype.func()
+                .map(|f| ids.contains(&f.id))
+                .unwrap_or(true)
+        });
+        self.globl_name_to_id.retain(|_k, v| {
+            ids.contains(v)
+        });
+    }
+
+    /// Names of private functions which aren't called from public and internal functions.
+    /// They are dropped unless `.pragma save-all-private-functions` is given.
+    pub fn unused_privates(&self) -> Vec<String> {
+        let ids = self.reachable_ids();
+        self.globl_name_to_object.iter()
+            .filter(|(_name, obj)| !obj.public && obj.dtype.func().map(|f| !ids.contains(&f.id)).unwrap_or(false))
+            .map(|(name, _obj)| name.clone())
+            .collect()
+    }
+
+    fn reachable_ids(&self) -> HashSet<u32> {
         let mut ids = HashSet::new();
         let publics_iter = self.globl_name_to_object.iter().filter_map(|obj| {
             obj.1.dtype.func()
@@ -1106,15 +1335,7 @@ impl ParseEngine {
         for func in self.internal_id_to_code.iter() {
             self.enum_calling_funcs(func.1, &mut ids);
         }
-
-        self.globl_name_to_object.retain(|_k, v| {
-            v.dtype.func()
-                .map(|f| ids.contains(&f.id))
-                .unwrap_or(true)
-        });
-        self.globl_name_to_id.retain(|_k, v| {
-            ids.contains(v)
-        });
+        ids
     }
 
     fn enum_calling_funcs(&self, func: &InternalFunc, ids: &mut HashSet<u32>) {
@@ -1313,6 +1534,75 @@ mod tests {
         assert_eq!(parser.is_ok(), true);
     }
 
+    #[test]
+    fn test_symbols() {
+        let sources = vec![Path::new("./tests/test_stdlib.tvm"), Path::new("./tests/test_macros.code")];
+        let parser = ParseEngine::new(sources, None).unwrap();
+        let symbols = parser.symbols();
+        let main = symbols.iter().find(|s| s.name == "main").unwrap();
+        assert_eq!(main.kind, SymbolKind::Public);
+        assert_eq!(main.id, 0x0D6E4079);
+        assert_eq!(main.pos, Some(DbgPos { filename: "test_macros.code".to_string(), line: 3, line_code: 3 }));
+        assert!(symbols.iter().any(|s| s.kind == SymbolKind::Internal && s.id == -1));
+    }
+
+    #[test]
+    fn test_method_id_crc16() {
+        let sources = vec![Path::new("./tests/test_stdlib.tvm"), Path::new("./tests/test_method_id_crc16.code")];
+        let parser = ParseEngine::new(sources, None).unwrap();
+        assert_eq!(parser.global_name(85143), Some("seqno".to_string()));
+        let parser = parser.with_method_id_scheme(MethodIdScheme::NameHash).unwrap();
+        assert_eq!(parser.global_name(85143), None);
+        assert_eq!(parser.global_name(gen_method_id(None, "seqno", MethodIdScheme::NameHash)), Some("seqno".to_string()));
+        let parser = parser.with_method_id_scheme(MethodIdScheme::Crc16).unwrap();
+        assert_eq!(parser.global_name(85143), Some("seqno".to_string()));
+    }
+
+    #[test]
+    fn test_unused_privates() {
+        let sources = vec![Path::new("./tests/test_unused_private.code")];
+        let parser = ParseEngine::new(sources, None).unwrap();
+        assert_eq!(parser.unused_privates(), vec!["unused".to_string()]);
+        let sources = vec![Path::new("./tests/test_stdlib.tvm"), Path::new("./tests/test_macros.code")];
+        let parser = ParseEngine::new(sources, None).unwrap();
+        assert!(parser.unused_privates().is_empty());
+    }
+
+    #[test]
+    fn test_abi_mismatches() {
+        let sources = vec![Path::new("./tests/test_stdlib_sol.tvm"), Path::new("./tests/Wallet.code")];
+        let abi = std::fs::read_to_string("./tests/Wallet.abi.json").unwrap();
+        let parser = ParseEngine::new(sources.clone(), Some(abi)).unwrap();
+        let (not_implemented, not_in_abi) = parser.abi_mismatches();
+        assert!(not_implemented.is_empty());
+        assert!(not_in_abi.contains(&"fallback".to_string()));
+        let abi = std::fs::read_to_string("./tests/test_complex.abi.json").unwrap();
+        let parser = ParseEngine::new(sources, Some(abi)).unwrap();
+        assert!(!parser.abi_mismatches().0.is_empty());
+    }
+
+    #[test]
+    fn test_include() {
+        let sources = vec![Path::new("./tests/test_include.code")];
+        let parser = ParseEngine::new(sources, None).unwrap();
+        let publics = parser.publics();
+        let body = publics.get(&0x0D6E4079).unwrap();
+        assert_eq!(body[0], Line::new("PUSHINT 10\n", "test_include.code", 6));
+
+        let sources = vec![Path::new("./tests/test_stdlib.tvm"), Path::new("./tests/test_include.code")];
+        assert!(ParseEngine::new(sources, None).is_ok());
+
+        let sources = vec![Path::new("./tests/test_include_cycle1.code")];
+        let err = ParseEngine::new(sources, None).err().unwrap();
+        match err.downcast_ref::<LinkerError>() {
+            Some(LinkerError::ParseError { file, line, message }) => {
+                assert_eq!((file.as_str(), *line), ("test_include_cycle2.code", 1));
+                assert!(message.starts_with("Include cycle detected"), "{}", message);
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+
     #[test]
     fn test_external_linking() {
         let sources = vec![Path::new("./tests/test_extlink_lib.tvm"),
@@ -1368,6 +1658,35 @@ mod tests {
         );
     }
 
+    fn parse_error_of(name: &str, source: &str) -> String {
+        let inputs = vec![ParseEngineInput { buf: Box::new(source.as_bytes()), name: name.to_string() }];
+        ParseEngine::new_generic(inputs, None).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_error_location() {
+        assert_eq!(
+            parse_error_of("pragma.code", ".selector\n.pragma unknown-pragma\n"),
+            "pragma.code:2: Unknown pragma: unknown-pragma"
+        );
+        assert_eq!(
+            parse_error_of("type.code", ".selector\nPUSHINT 1\n.type main\n"),
+            "type.code:3: invalid syntax: .type main"
+        );
+        assert_eq!(
+            parse_error_of("empty.code", "PUSHINT 1\n"),
+            "empty.code: input file has no assembler definitions"
+        );
+
+        let inputs = vec![ParseEngineInput { buf: Box::new(".selector\nPUSHINT 1\n.type main\n".as_bytes()), name: "type.code".to_string() }];
+        let err = ParseEngine::new_generic(inputs, None).err().unwrap();
+        assert_eq!(err.downcast_ref::<LinkerError>(), Some(&LinkerError::ParseError {
+            file: "type.code".to_string(),
+            line: 3,
+            message: "invalid syntax: .type main".to_string(),
+        }));
+    }
+
     #[test]
     fn test_compute() {
         let sources = vec![Path::new("./tests/test_stdlib.tvm"),
@@ -13,18 +13,33 @@
 use failure::format_err;
 use ton_block::*;
 use ton_types::write_boc;
-use ton_types::{BuilderData, Cell, Result};
+use ton_types::{BuilderData, Cell, Result, SliceData, UInt256};
+use std::collections::HashSet;
 
-fn get_version(root: &Cell) -> Result<String> {
+fn version_cell(root: &Cell) -> Result<Cell> {
     let cell1 = root.reference(0).map_err(|e| format_err!("not found ({})", e))?;
-    let cell2 = cell1.reference(1).map_err(|e| format_err!("not found ({})", e))?;
-    let bytes = cell2.data();
+    cell1.reference(1).map_err(|e| format_err!("not found ({})", e))
+}
+
+fn decode_version(cell: &Cell) -> Result<String> {
+    let bytes = cell.data();
     match String::from_utf8(bytes.to_vec()) {
         Ok(string) => if string.is_empty() { Ok("<empty>".to_string()) } else { Ok(string) },
         Err(e) => Err(format_err!("decoding failed ({})", e))
     }
 }
 
+fn get_version(root: &Cell) -> Result<String> {
+    decode_version(&version_cell(root)?)
+}
+
+fn try_get_version_plain(root: &Cell) -> Result<Option<String>> {
+    match version_cell(root) {
+        Ok(cell) => decode_version(&cell).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
 pub fn get_version_mycode_aware(root: Option<&Cell>) -> Result<String> {
     let root = root.ok_or_else(|| format_err!("not found (empty root)"))?;
     match get_version(root) {
@@ -36,6 +51,29 @@ pub fn get_version_mycode_aware(root: Option<&Cell>) -> Result<String> {
     }
 }
 
+/// Same as `get_version_mycode_aware`, but returns `Ok(None)` if the code has no version stamped.
+/// An error is returned only if the version cell can't be decoded.
+pub fn try_get_version(root: Option<&Cell>) -> Result<Option<String>> {
+    let root = match root {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+    match try_get_version_plain(root) {
+        Ok(Some(res)) => Ok(Some(res)),
+        res => match root.reference(1) {
+            Ok(root) => try_get_version_plain(&root),
+            Err(_) => res,
+        }
+    }
+}
+
+/// Reads the salt of the code, which is the third reference of the internal selector
+/// after the methods dictionary and the version.
+pub fn get_code_salt(root: &Cell) -> Option<Cell> {
+    let salt = |root: &Cell| root.reference(0).ok()?.reference(2).ok();
+    salt(root).or_else(|| salt(&root.reference(1).ok()?))
+}
+
 pub fn state_init_printer(state: &StateInit) -> String {
     format!("StateInit\n split_depth: {}\n special: {}\n data: {}\n code: {}\n code_hash: {}\n data_hash: {}\n code_depth: {}\n data_depth: {}\n version: {}\n lib:  {}\n",
         state.split_depth.as_ref().map_or("None".to_string(), |x| x.as_u32().to_string()),
@@ -46,7 +84,10 @@ pub fn state_init_printer(state: &StateInit) -> String {
         state.data.as_ref().map(|code| code.repr_hash().to_hex_string()).unwrap_or_else(|| "None".to_string()),
         state.code.as_ref().map(|code| code.repr_depth().to_string()).unwrap_or_else(|| "None".to_string()),
         state.data.as_ref().map(|code| code.repr_depth().to_string()).unwrap_or_else(|| "None".to_string()),
-        get_version_mycode_aware(state.code.as_ref()).unwrap_or_else(|_| "None".to_string()),
+        match try_get_version(state.code.as_ref()) {
+            Ok(version) => version.unwrap_or_else(|| "None".to_string()),
+            Err(e) => format!("<{}>", e),
+        },
         tree_of_cells_into_base64(state.library.root()),
     )
 }
@@ -63,12 +104,158 @@ pub fn tree_of_cells_into_base64(root_cell: Option<&Cell>) -> String {
     }
 }
 
+/// Prints the tree of cells one cell per line, indented by depth: hash, data length in bits and data in hex.
+pub fn cell_tree_printer(root: &Cell) -> String {
+    fn print_cell(cell: &Cell, depth: usize, out: &mut String) {
+        out.push_str(&format!("{}{} ({} bits, {} refs): {}\n",
+            "  ".repeat(depth),
+            cell.repr_hash().to_hex_string(),
+            cell.bit_length(),
+            cell.references_count(),
+            cell.to_hex_string(true),
+        ));
+        for i in 0..cell.references_count() {
+            if let Ok(child) = cell.reference(i) {
+                print_cell(&child, depth + 1, out);
+            }
+        }
+    }
+    let mut out = String::new();
+    print_cell(root, 0, &mut out);
+    out
+}
+
+/// Prints the state labelling its fields according to the `StateInit` TL-B scheme,
+/// cells of code, data and library are dumped recursively with indexes of their references.
+pub fn state_init_tlb_dump(state: &StateInit) -> String {
+    fn dump_cell(cell: &Cell, label: &str, depth: usize, out: &mut String) {
+        out.push_str(&format!("{}{}: {} bits, {} refs, hash {}: x{{{}}}\n",
+            "  ".repeat(depth),
+            label,
+            cell.bit_length(),
+            cell.references_count(),
+            cell.repr_hash().to_hex_string(),
+            cell.to_hex_string(true),
+        ));
+        for i in 0..cell.references_count() {
+            if let Ok(child) = cell.reference(i) {
+                dump_cell(&child, &format!("ref[{}]", i), depth + 1, out);
+            }
+        }
+    }
+    fn dump_maybe_ref(cell: Option<&Cell>, label: &str, out: &mut String) {
+        match cell {
+            Some(cell) => {
+                out.push_str(&format!("  {}:(Maybe ^Cell) just\n", label));
+                dump_cell(cell, "^Cell", 2, out);
+            }
+            None => out.push_str(&format!("  {}:(Maybe ^Cell) nothing\n", label)),
+        }
+    }
+    let mut out = String::from("StateInit\n");
+    match &state.split_depth {
+        Some(depth) => out.push_str(&format!("  split_depth:(Maybe (## 5)) just {}\n", depth.as_u32())),
+        None => out.push_str("  split_depth:(Maybe (## 5)) nothing\n"),
+    }
+    match &state.special {
+        Some(special) => out.push_str(&format!("  special:(Maybe TickTock) just tick:{} tock:{}\n", special.tick, special.tock)),
+        None => out.push_str("  special:(Maybe TickTock) nothing\n"),
+    }
+    dump_maybe_ref(state.code.as_ref(), "code", &mut out);
+    dump_maybe_ref(state.data.as_ref(), "data", &mut out);
+    match state.library.root() {
+        Some(root) => {
+            out.push_str("  library:(HashmapE 256 SimpleLib) root\n");
+            dump_cell(root, "^Cell", 2, &mut out);
+        }
+        None => out.push_str("  library:(HashmapE 256 SimpleLib) empty\n"),
+    }
+    out
+}
+
+/// Lists unique cells (by representation hash) of code, data and library of the state
+/// in depth-first order: hash, count of references and data length in bits.
+pub fn unique_cells(state: &StateInit) -> Vec<(UInt256, usize, usize)> {
+    fn visit(cell: &Cell, seen: &mut HashSet<UInt256>, out: &mut Vec<(UInt256, usize, usize)>) {
+        let hash = cell.repr_hash();
+        if !seen.insert(hash.clone()) {
+            return
+        }
+        out.push((hash, cell.references_count(), cell.bit_length()));
+        for i in 0..cell.references_count() {
+            if let Ok(child) = cell.reference(i) {
+                visit(&child, seen, out);
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    for root in [state.code.as_ref(), state.data.as_ref(), state.library.root()].into_iter().flatten() {
+        visit(root, &mut seen, &mut out);
+    }
+    out
+}
+
+/// Unit used to print nanogram values in a human-readable form, e.g. `1.5 TON`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub symbol: String,
+    pub decimals: usize,
+}
+
+impl Token {
+    pub fn new(symbol: &str, decimals: usize) -> Self {
+        Token { symbol: symbol.to_string(), decimals }
+    }
+
+    /// Formats the amount given in the smallest units, e.g. `1500000000` as `1.5 TON` for 9 decimals.
+    pub fn format(&self, amount: &str) -> String {
+        let amount = format!("{:0>width$}", amount, width = self.decimals + 1);
+        let (int, frac) = amount.split_at(amount.len() - self.decimals);
+        let frac = frac.trim_end_matches('0');
+        if frac.is_empty() {
+            format!("{} {}", int, self.symbol)
+        } else {
+            format!("{}.{} {}", int, frac, self.symbol)
+        }
+    }
+}
+
+impl std::str::FromStr for Token {
+    type Err = failure::Error;
+    /// Parses `symbol[:decimals]`, 9 decimals are used by default.
+    fn from_str(s: &str) -> Result<Self> {
+        let (symbol, decimals) = match s.split_once(':') {
+            Some((symbol, decimals)) => (symbol, decimals.parse::<usize>()
+                .map_err(|e| format_err!("invalid count of decimals in token {}: {}", s, e))?),
+            None => (s, 9),
+        };
+        if symbol.is_empty() {
+            return Err(format_err!("token symbol is empty"))
+        }
+        Ok(Token::new(symbol, decimals))
+    }
+}
+
+/// Options of `msg_printer_ex`.
+#[derive(Clone, Debug, Default)]
+pub struct MsgPrinterOptions {
+    /// If set, nanogram values are followed by the amount in this unit.
+    pub token: Option<Token>,
+    /// If set, addresses are printed in raw `workchain:hex` form.
+    pub raw_addresses: bool,
+}
+
 pub fn msg_printer(msg: &Message) -> Result<String> {
+    msg_printer_ex(msg, &MsgPrinterOptions::default())
+}
+
+pub fn msg_printer_ex(msg: &Message, options: &MsgPrinterOptions) -> Result<String> {
     let mut b = BuilderData::new();
     msg.write_to(&mut b)?;
     let bytes = write_boc(&b.into_cell()?)?;
     Ok(format!("message header\n{}init  : {}\nbody  : {}\nbody_hex: {}\nbody_base64: {}\nboc_base64: {}\n",
-        print_msg_header(msg.header()),
+        print_msg_header(msg.header(), options),
         msg.state_init().as_ref().map(|x| {
             state_init_printer(x)
         }).unwrap_or_else(|| "None".to_string()),
@@ -88,40 +275,112 @@ pub fn msg_printer(msg: &Message) -> Result<String> {
     ))
 }
 
-fn print_msg_header(header: &CommonMsgInfo) -> String {
+/// Prints an output action with its parameters, the message of `SendMsg` is printed by `msg_printer`.
+pub fn print_action(action: &OutAction) -> Result<String> {
+    Ok(match action {
+        OutAction::SendMsg { mode, out_msg } =>
+            format!("Action(SendMsg): mode {}\n{}", mode, msg_printer(out_msg)?),
+        OutAction::SetCode { new_code } =>
+            format!("Action(SetCode): code hash {:x}", new_code.repr_hash()),
+        OutAction::ReserveCurrency { mode, value } =>
+            format!("Action(ReserveCurrency): mode {}, value {}", mode, print_cc(value)),
+        OutAction::ChangeLibrary { mode, .. } =>
+            format!("Action(ChangeLibrary): mode {}", mode),
+        _ => "Action(Unknown)".to_string(),
+    })
+}
+
+/// Decodes the list of output actions from the c5 register and prints them in the order of execution.
+pub fn print_actions(actions: &Cell) -> Result<String> {
+    let actions = OutActions::construct_from(&mut SliceData::load_cell(actions.clone())?)?;
+    let mut result = String::new();
+    for action in actions.iter() {
+        result += &print_action(action)?;
+        result += "\n";
+    }
+    Ok(result)
+}
+
+fn print_msg_header(header: &CommonMsgInfo, options: &MsgPrinterOptions) -> String {
+    let token = options.token.as_ref();
     match header {
         CommonMsgInfo::IntMsgInfo(header) => {
             format!("   ihr_disabled: {}\n", header.ihr_disabled) +
             &format!("   bounce      : {}\n", header.bounce) +
             &format!("   bounced     : {}\n", header.bounced) +
-            &format!("   source      : {}\n", &header.src) +
-            &format!("   destination : {}\n", &header.dst) +
-            &format!("   value       : {}\n", print_cc(&header.value)) +
-            &format!("   ihr_fee     : {}\n", print_grams(&header.ihr_fee)) +
-            &format!("   fwd_fee     : {}\n", print_grams(&header.fwd_fee)) +
+            &format!("   source      : {}\n", print_int_or_none(&header.src, options)) +
+            &format!("   destination : {}\n", print_int(&header.dst, options)) +
+            &format!("   value       : {}\n", print_cc_ex(&header.value, token)) +
+            &format!("   ihr_fee     : {}\n", print_grams_ex(&header.ihr_fee, token)) +
+            &format!("   fwd_fee     : {}\n", print_grams_ex(&header.fwd_fee, token)) +
+            &format!("   total_fee   : {}\n", print_total_fee(&[&header.ihr_fee, &header.fwd_fee], token)) +
             &format!("   created_lt  : {}\n", header.created_lt) +
             &format!("   created_at  : {}\n", header.created_at)
         },
         CommonMsgInfo::ExtInMsgInfo(header) => {
-            format!( "   source      : {}\n", &header.src) +
-            &format!("   destination : {}\n", &header.dst) +
-            &format!("   import_fee  : {}\n", print_grams(&header.import_fee))
+            format!( "   source      : {}\n", print_ext(&header.src, options)) +
+            &format!("   destination : {}\n", print_int(&header.dst, options)) +
+            &format!("   import_fee  : {}\n", print_grams_ex(&header.import_fee, token)) +
+            &format!("   total_fee   : {}\n", print_total_fee(&[&header.import_fee], token))
         },
         CommonMsgInfo::ExtOutMsgInfo(header) => {
-            format!( "   source      : {}\n", &header.src) +
-            &format!("   destination : {}\n", &header.dst) +
+            format!( "   source      : {}\n", print_int_or_none(&header.src, options)) +
+            &format!("   destination : {}\n", print_ext(&header.dst, options)) +
             &format!("   created_lt  : {}\n", header.created_lt) +
             &format!("   created_at  : {}\n", header.created_at)
         }
     }
 }
 
+fn print_int(addr: &MsgAddressInt, options: &MsgPrinterOptions) -> String {
+    if options.raw_addresses {
+        format!("{}:{}", addr.get_workchain_id(), hex::encode(addr.address().get_bytestring(0)))
+    } else {
+        addr.to_string()
+    }
+}
+
+fn print_int_or_none(addr: &MsgAddressIntOrNone, options: &MsgPrinterOptions) -> String {
+    match addr {
+        MsgAddressIntOrNone::Some(addr) => print_int(addr, options),
+        MsgAddressIntOrNone::None if options.raw_addresses => "None".to_string(),
+        MsgAddressIntOrNone::None => addr.to_string(),
+    }
+}
+
+fn print_ext(addr: &MsgAddressExt, options: &MsgPrinterOptions) -> String {
+    match addr {
+        MsgAddressExt::AddrExtern(ext) if options.raw_addresses =>
+            format!(":{}", hex::encode(ext.external_address.get_bytestring(0))),
+        MsgAddressExt::AddrNone if options.raw_addresses => "None".to_string(),
+        _ => addr.to_string(),
+    }
+}
+
 fn print_grams(grams: &Grams) -> String {
     grams.to_string()
 }
 
+fn print_grams_ex(grams: &Grams, token: Option<&Token>) -> String {
+    match token {
+        Some(token) => format!("{} ({})", print_grams(grams), token.format(&grams.to_string())),
+        None => print_grams(grams),
+    }
+}
+
+/// Sums the fees and prints the total in nanograms and in decimal form, TON is used if no token is given.
+fn print_total_fee(fees: &[&Grams], token: Option<&Token>) -> String {
+    let total = fees.iter().map(|fee| fee.as_u128()).sum::<u128>().to_string();
+    let ton = Token::new("TON", 9);
+    format!("{} ({})", total, token.unwrap_or(&ton).format(&total))
+}
+
 fn print_cc(cc: &CurrencyCollection) -> String {
-    let mut result = print_grams(&cc.grams);
+    print_cc_ex(cc, None)
+}
+
+fn print_cc_ex(cc: &CurrencyCollection, token: Option<&Token>) -> String {
+    let mut result = print_grams_ex(&cc.grams, token);
     if !cc.other.is_empty() {
         result += " other: {";
         cc.other.iterate_with_keys(|key: u32, value| {
@@ -145,3 +404,88 @@ fn check_output_for_money() {
     assert_eq!(print_grams(&cc.grams), "72057594037927935");
     assert_eq!(print_cc(&cc), r#"72057594037927935 other: { "12": "125", "17": "340282366920938463463374607431768213356", "4294967295": "452312848583266388373324160190187140051835877600158453279131187530910662655" }"#);
 }
+
+#[test]
+fn check_output_for_token() {
+    let token = Token::new("TON", 9);
+    assert_eq!(token.format("1500000000"), "1.5 TON");
+    assert_eq!(token.format("2000000000"), "2 TON");
+    assert_eq!(token.format("1"), "0.000000001 TON");
+    assert_eq!(token.format("0"), "0 TON");
+    assert_eq!(Token::new("X", 0).format("15"), "15 X");
+    let cc = CurrencyCollection::with_grams(1500000000);
+    assert_eq!(print_cc_ex(&cc, Some(&token)), "1500000000 (1.5 TON)");
+    assert_eq!("TON".parse::<Token>().unwrap(), token);
+    assert_eq!("USDT:6".parse::<Token>().unwrap(), Token::new("USDT", 6));
+    assert!(":6".parse::<Token>().is_err());
+}
+
+#[test]
+fn check_total_fee() {
+    let ihr_fee = Grams::from(500000000u64);
+    let fwd_fee = Grams::from(1000000000u64);
+    assert_eq!(print_total_fee(&[&ihr_fee, &fwd_fee], None), "1500000000 (1.5 TON)");
+    assert_eq!(print_total_fee(&[&Grams::from(1000u64)], Some(&Token::new("USDT", 3))), "1000 (1 USDT)");
+    let mut header = InternalMessageHeader::default();
+    header.ihr_fee = ihr_fee;
+    header.fwd_fee = fwd_fee;
+    let printed = print_msg_header(&CommonMsgInfo::IntMsgInfo(header), &MsgPrinterOptions::default());
+    assert!(printed.contains("   total_fee   : 1500000000 (1.5 TON)\n"));
+}
+
+#[test]
+fn check_print_actions() {
+    let code = BuilderData::with_raw(vec![0xAA], 8).unwrap().into_cell().unwrap();
+    let mut actions = OutActions::default();
+    actions.push_back(OutAction::new_reserve(2, CurrencyCollection::with_grams(100)));
+    actions.push_back(OutAction::new_set(code.clone()));
+    let printed = print_actions(&actions.serialize().unwrap()).unwrap();
+    let lines = printed.lines().collect::<Vec<_>>();
+    assert_eq!(lines, vec![
+        "Action(ReserveCurrency): mode 2, value 100".to_string(),
+        format!("Action(SetCode): code hash {:x}", code.repr_hash()),
+    ]);
+}
+
+#[test]
+fn check_raw_addresses() {
+    let options = MsgPrinterOptions { raw_addresses: true, ..Default::default() };
+    let addr = MsgAddressInt::with_standart(None, -1, [0x11; 32].into()).unwrap();
+    assert_eq!(print_int(&addr, &options), format!("-1:{}", "11".repeat(32)));
+    assert_eq!(print_int_or_none(&MsgAddressIntOrNone::None, &options), "None");
+    assert_eq!(print_ext(&MsgAddressExt::AddrNone, &options), "None");
+}
+
+#[test]
+fn check_state_init_tlb_dump() {
+    let leaf = BuilderData::with_raw(vec![0xAA], 8).unwrap().into_cell().unwrap();
+    let mut b = BuilderData::new();
+    b.checked_append_reference(leaf).unwrap();
+    let mut state = StateInit::default();
+    state.set_code(b.into_cell().unwrap());
+    let dump = state_init_tlb_dump(&state);
+    let lines = dump.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "StateInit");
+    assert_eq!(lines[1], "  split_depth:(Maybe (## 5)) nothing");
+    assert_eq!(lines[2], "  special:(Maybe TickTock) nothing");
+    assert_eq!(lines[3], "  code:(Maybe ^Cell) just");
+    assert!(lines[4].starts_with("    ^Cell: 0 bits, 1 refs, hash "));
+    assert!(lines[5].starts_with("      ref[0]: 8 bits, 0 refs, hash ") && lines[5].to_lowercase().ends_with(": x{aa}"));
+    assert_eq!(lines[6], "  data:(Maybe ^Cell) nothing");
+    assert_eq!(lines[7], "  library:(HashmapE 256 SimpleLib) empty");
+}
+
+#[test]
+fn check_unique_cells() {
+    let leaf = BuilderData::with_raw(vec![0xAA], 8).unwrap().into_cell().unwrap();
+    let mut b = BuilderData::new();
+    b.checked_append_reference(leaf.clone()).unwrap();
+    b.checked_append_reference(leaf.clone()).unwrap();
+    let mut state = StateInit::default();
+    state.set_code(b.into_cell().unwrap());
+    state.set_data(leaf.clone());
+    let cells = unique_cells(&state);
+    assert_eq!(cells.len(), 2);
+    assert_eq!(cells[0].1, 2);
+    assert_eq!(cells[1], (leaf.repr_hash(), 0, 8));
+}
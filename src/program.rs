@@ -12,64 +12,419 @@
  */
 use base64::encode;
 use ed25519_dalek::*;
-use failure::format_err;
+use failure::{bail, format_err};
 use std::fs::File;
 use std::io::{Read, Write};
-use std::collections::HashMap;
-use std::time::SystemTime;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use serde::Serialize;
+use std::time::{Instant, SystemTime};
 use ton_block::*;
 use ton_labs_assembler::{Line, Lines, DbgInfo, Engine};
 use ton_types::{
-    read_boc, Cell, SliceData, BuilderData, IBitstring, Result,
+    read_boc, write_boc, BocWriter, Cell, CellType, SliceData, UInt256, BuilderData, IBitstring, Result, Status,
     dictionary::{HashmapE, HashmapType},
 };
-use crate::parser::{ptr_to_builder, ParseEngine, ParseEngineResults, SelectorVariant};
-use crate::printer::tree_of_cells_into_base64;
+use abi_json::json_abi::update_contract_data;
+use crate::abi::MethodIdScheme;
+use crate::error::LinkerError;
+use crate::parser::{ptr_to_builder, ParseEngine, ParseEngineInput, ParseEngineResults, SelectorVariant, SymbolKind, Symbols};
+use crate::printer::{cell_tree_printer, tree_of_cells_into_base64};
+
+lazy_static! {
+    /// Compiled fixed selectors, they don't depend on the program being compiled.
+    static ref FIXED_CODE: Mutex<HashMap<String, (SliceData, DbgInfo)>> = Mutex::new(HashMap::new());
+}
+
+/// Maximum depth of a cell tree accepted by the network.
+pub const MAX_CELL_DEPTH: u16 = 1024;
+/// Default key width of the data dictionary.
+pub const DATA_KEY_BITS: usize = 64;
 
 const XMODEM: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM);
 
+/// Controls the messages printed to stdout while compiling and saving contracts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verbosity {
+    /// Human-readable messages.
+    Normal,
+    /// Only machine-readable JSON output.
+    Silent,
+    /// Nothing is printed, the results are only returned.
+    Quiet,
+}
+
+/// Flags of the bag of cells written to TVC files, compared to Fift's `boc+>B` flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SerializationFormat {
+    /// No index and no CRC, the same as Fift's `boc>B` (flags 0).
+    #[default]
+    Default,
+    /// CRC32-C of the bag is appended, the same as Fift's `2 boc+>B`.
+    Crc,
+    /// Cells index is included and CRC32-C is appended, the same as Fift's `3 boc+>B`.
+    IndexCrc,
+    /// The same flags as `IndexCrc`, for pipelines migrating from Fift which writes both the index and CRC.
+    Fift,
+}
+
+/// Layout of the code selector: the legacy one is built from the `.selector` section of the sources.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SelectorKind {
+    /// Legacy if the sources contain a `.selector` section, modern otherwise.
+    #[default]
+    Auto,
+    Legacy,
+    /// The `.selector` section is ignored.
+    Modern,
+}
+
+impl std::str::FromStr for SelectorKind {
+    type Err = failure::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(SelectorKind::Auto),
+            "legacy" => Ok(SelectorKind::Legacy),
+            "modern" => Ok(SelectorKind::Modern),
+            _ => bail!("unknown selector kind {}, expected one of: auto, legacy, modern", s),
+        }
+    }
+}
+
+impl std::str::FromStr for SerializationFormat {
+    type Err = failure::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(SerializationFormat::Default),
+            "crc" => Ok(SerializationFormat::Crc),
+            "index_crc" => Ok(SerializationFormat::IndexCrc),
+            "fift" => Ok(SerializationFormat::Fift),
+            _ => bail!("unknown BOC format {}, expected one of: default, crc, index_crc, fift", s),
+        }
+    }
+}
+
+impl SerializationFormat {
+    pub fn serialize(&self, root: &Cell) -> Result<Vec<u8>> {
+        if *self == SerializationFormat::Default {
+            return write_boc(root)
+        }
+        let mut bytes = Vec::new();
+        self.write_to(root, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Writes the bag of cells directly to `dest` without buffering it.
+    pub fn write_to(&self, root: &Cell, mut dest: &mut dyn Write) -> Status {
+        let (include_index, include_crc) = match self {
+            SerializationFormat::Default => (false, false),
+            SerializationFormat::Crc => (false, true),
+            SerializationFormat::IndexCrc | SerializationFormat::Fift => (true, true),
+        };
+        BocWriter::with_root(root)?.write_ex(&mut dest, include_index, include_crc, None, None)
+    }
+}
+
+/// File system used to load and save compiled contracts, e.g. a virtual one when running in a browser.
+pub trait Fs {
+    fn read(&self, path: &str) -> std::io::Result<Vec<u8>>;
+    fn write(&self, path: &str, data: &[u8]) -> std::io::Result<()>;
+    /// Writes the file with the given function. By default the data is collected into a buffer
+    /// and passed to `write`, file systems able to stream it should override this.
+    fn write_with(&self, path: &str, write: &mut dyn FnMut(&mut dyn Write) -> Status) -> Status {
+        let mut data = Vec::new();
+        write(&mut data)?;
+        Ok(self.write(path, &data)?)
+    }
+}
+
+/// File system of the host, used by default.
+pub struct NativeFs;
+
+impl Fs for NativeFs {
+    fn read(&self, path: &str) -> std::io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+    fn write(&self, path: &str, data: &[u8]) -> std::io::Result<()> {
+        std::fs::write(path, data)
+    }
+    fn write_with(&self, path: &str, write: &mut dyn FnMut(&mut dyn Write) -> Status) -> Status {
+        let mut file = std::io::BufWriter::new(File::create(path)?);
+        write(&mut file)?;
+        Ok(file.flush()?)
+    }
+}
+
+/// Transformation applied to the compiled code cell before it is put into the state.
+pub type CodePostprocessor = Box<dyn Fn(Cell) -> Result<Cell>>;
+
 pub struct Program {
     language: Option<String>,
     engine: ParseEngineResults,
     keypair: Option<Keypair>,
     pub dbgmap: DbgInfo,
     print_code: bool,
-    silent: bool,
+    verbosity: Verbosity,
+    split_boc: bool,
+    stdout_hex: bool,
+    merge_data: bool,
+    profile: bool,
+    boc_format: SerializationFormat,
+    selector_kind: SelectorKind,
+    entry_selector: Option<Lines>,
+    max_cell_depth: u16,
+    data_key_bits: usize,
+    version: Option<String>,
+    emit_version: bool,
+    external_methods: BTreeMap<u32, Cell>,
+    deploy_code: Option<Cell>,
+    code_salt: Option<Cell>,
+    data_overflow: Option<Cell>,
+    code_postprocessor: Option<CodePostprocessor>,
+    debug_guards: bool,
+    stack_deltas: HashMap<u32, i32>,
+    check_abi: bool,
+    warnings: Vec<String>,
+    fs: Box<dyn Fs>,
     assembler: Engine,
 }
 
+fn build_assembler(engine: &ParseEngineResults) -> Result<Engine> {
+    let mut assembler = Engine::new(Vec::new());
+    for name in engine.postorder_fragments() {
+        let lines = engine.fragments().get(name).unwrap();
+        assembler.build(Some(format!("__{}", name)), lines.clone())
+            .map_err(|e| LinkerError::CompileError(format!("Failed to assemble {}: {}", name, e)))?;
+    }
+    Ok(assembler)
+}
+
 impl Program {
     pub fn new(parser: ParseEngine) -> Result<Self> {
         let engine = ParseEngineResults::new(parser);
-        let mut assembler = Engine::new(Vec::new());
-        for name in engine.postorder_fragments() {
-            let lines = engine.fragments().get(name).unwrap();
-            assembler.build(Some(format!("__{}", name)), lines.clone())
-                .map_err(|e| format_err!("Failed to assemble {}: {}", name, e))?;
-        }
+        let assembler = build_assembler(&engine)?;
         Ok(Program {
             language: None,
             engine,
             keypair: None,
             dbgmap: DbgInfo::default(),
             print_code: false,
-            silent: false,
+            verbosity: Verbosity::Normal,
+            split_boc: false,
+            stdout_hex: false,
+            merge_data: false,
+            profile: false,
+            boc_format: SerializationFormat::Default,
+            selector_kind: SelectorKind::Auto,
+            entry_selector: None,
+            max_cell_depth: MAX_CELL_DEPTH,
+            data_key_bits: DATA_KEY_BITS,
+            version: None,
+            emit_version: true,
+            external_methods: BTreeMap::new(),
+            deploy_code: None,
+            code_salt: None,
+            data_overflow: None,
+            code_postprocessor: None,
+            debug_guards: false,
+            stack_deltas: HashMap::new(),
+            check_abi: false,
+            warnings: Vec::new(),
+            fs: Box::new(NativeFs),
             assembler,
         })
     }
 
+    /// Compiles the code and disassembles it, each instruction is annotated with its `file:line`
+    /// from the debug map.
+    pub fn dump_annotated_asm(&mut self) -> Result<String> {
+        let code = self.compile_asm(false)?;
+        crate::disasm::commands::disasm_annotated(&mut SliceData::load_cell(code)?, &self.dbgmap)
+    }
+
+    /// Parses the sources again calculating ids of public functions missing in the ABI with the given
+    /// scheme, it takes precedence over `.pragma method-id-crc16`.
+    pub fn set_method_id_scheme(&mut self, scheme: MethodIdScheme) -> Status {
+        self.engine = self.engine.with_method_id_scheme(scheme)?;
+        self.assembler = build_assembler(&self.engine)?;
+        Ok(())
+    }
+
+    /// Parses the program from assembly text instead of files, e.g. in tests.
+    /// `.include` directives are not supported, the text must contain everything the program needs.
+    pub fn from_str(asm: &str, abi_json: Option<String>) -> Result<Self> {
+        let input = ParseEngineInput { buf: Box::new(std::io::Cursor::new(asm.to_string())), name: "<string>".to_string() };
+        Program::new(ParseEngine::new_generic(vec![input], abi_json)?)
+    }
+
     pub fn set_print_code(&mut self, print_code: bool) {
         self.print_code = print_code;
     }
 
     pub fn set_silent(&mut self, silent: bool) {
-        self.silent = silent;
+        self.verbosity = if silent { Verbosity::Silent } else { Verbosity::Normal };
+    }
+
+    pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
+    }
+
+    /// Makes `compile_to_file_ex` also save code and data cells to `<name>.code.boc` and `<name>.data.boc`.
+    pub fn set_split_boc(&mut self, split_boc: bool) {
+        self.split_boc = split_boc;
+    }
+
+    /// Makes `compile_to_file_ex` also print the saved BOC as a hex string to stdout,
+    /// e.g. to paste it into an explorer or Fift. Nothing is printed unless the verbosity is normal.
+    pub fn set_stdout_hex(&mut self, stdout_hex: bool) {
+        self.stdout_hex = stdout_hex;
     }
 
     pub fn set_language(&mut self, lang: Option<&str>) {
         self.language = lang.map(|s| s.to_owned());
     }
 
+    /// Makes `compile_to_file_ex` merge the data dictionary from `data_filename` into the computed
+    /// data instead of replacing it. Keys present in both dictionaries are reported as an error.
+    pub fn set_merge_data(&mut self, merge_data: bool) {
+        self.merge_data = merge_data;
+    }
+
+    /// Makes the compilation print elapsed time of its stages.
+    pub fn set_profile(&mut self, profile: bool) {
+        self.profile = profile;
+    }
+
+    fn print_elapsed(&self, stage: &str, start: Instant) {
+        if self.profile {
+            eprintln!("Profile: {}: {:?}", stage, start.elapsed());
+        }
+    }
+
+    pub fn set_boc_format(&mut self, format: SerializationFormat) {
+        self.boc_format = format;
+    }
+
+    /// Chooses the selector layout explicitly instead of detecting it by the `.selector` section.
+    pub fn set_selector_kind(&mut self, kind: SelectorKind) {
+        self.selector_kind = kind;
+    }
+
+    /// Replaces the built-in entry selector dispatching on the transaction type. The code gets the
+    /// internal selector and the entry points for 0, -1 and -2 as its references in this order,
+    /// and must fit into a single cell.
+    pub fn set_entry_selector(&mut self, lines: Lines) {
+        self.entry_selector = Some(lines);
+    }
+
+    /// Sets the maximum depth of code and data cell trees checked before the contract is saved.
+    pub fn set_max_cell_depth(&mut self, depth: u16) {
+        self.max_cell_depth = depth;
+    }
+
+    /// Sets the key width of the data dictionary built by `data`, 64 bits by default.
+    /// The public key slot is zero-extended to wider keys.
+    pub fn set_data_key_bits(&mut self, bits: usize) {
+        self.data_key_bits = bits;
+    }
+
+    /// Replaces the file system used by `compile_to_file_ex` to load data and save the contract.
+    pub fn set_fs(&mut self, fs: Box<dyn Fs>) {
+        self.fs = fs;
+    }
+
+    /// Makes the compilation fail if a function of the ABI is not implemented in the sources.
+    /// Public functions missing in the ABI, e.g. `fallback`, are only reported.
+    pub fn set_check_abi(&mut self, check_abi: bool) {
+        self.check_abi = check_abi;
+    }
+
+    /// Checks that the functions of the ABI are implemented as public functions in the sources.
+    pub fn check_abi(&mut self) -> Status {
+        let (not_implemented, not_in_abi) = self.engine.abi_mismatches();
+        for name in not_in_abi {
+            self.warn(format!("public function {} is missing in the ABI", name));
+        }
+        if !not_implemented.is_empty() {
+            return Err(LinkerError::CompileError(format!("ABI functions are not implemented in the sources: {}", not_implemented.join(", "))).into());
+        }
+        Ok(())
+    }
+
+    /// Salts the code, so that contracts with the same sources get different addresses. The salt is
+    /// appended to the internal selector after the version, the same way as `tvm.setCodeSalt` does.
+    pub fn set_code_salt(&mut self, salt: Cell) {
+        self.code_salt = Some(salt);
+    }
+
+    /// Makes `compile_to_state` pass the compiled code through `f`, e.g. to wrap it for a specific
+    /// VM version. The result is also what `--print_code` and the deploy code reference get.
+    pub fn set_code_postprocessor(&mut self, f: CodePostprocessor) {
+        self.code_postprocessor = Some(f);
+    }
+
+    /// Sets the keypair whose public key is stored to the contract data.
+    pub fn set_keypair(&mut self, keypair: Keypair) {
+        self.keypair = Some(keypair);
+    }
+
+    /// Loads the keypair from a `{"public": "...", "secret": "..."}` JSON file with hex keys,
+    /// the format of key files generated by tonos-cli.
+    pub fn set_keypair_from_keystore(&mut self, path: &str) -> Status {
+        self.keypair = Some(crate::keyman::KeypairManager::from_file(path)?.drain());
+        Ok(())
+    }
+
+    /// Makes the methods with a known stack delta, see `set_stack_delta`, check that they leave the stack
+    /// balanced: the depth on return should be the depth on entry plus the delta, see `methdict::guard_method`.
+    /// For debugging builds only, the guards cost gas.
+    pub fn set_debug_guards(&mut self, debug_guards: bool) {
+        self.debug_guards = debug_guards;
+    }
+
+    pub fn debug_guards(&self) -> bool {
+        self.debug_guards
+    }
+
+    /// Sets the number of results minus the number of arguments of the method with the given id,
+    /// only such methods get the debug guards.
+    pub fn set_stack_delta(&mut self, id: u32, delta: i32) {
+        self.stack_deltas.insert(id, delta);
+    }
+
+    pub fn stack_delta(&self, id: u32) -> Option<i32> {
+        self.stack_deltas.get(&id).copied()
+    }
+
+    /// Places `cell` into the second reference of the data cell, after the data dictionary, e.g. for
+    /// static tables which don't fit into the dictionary. The code reference added by `set_deploy_code`
+    /// follows it.
+    pub fn set_data_overflow(&mut self, cell: Cell) {
+        self.data_overflow = Some(cell);
+    }
+
+    /// Makes the contract deploy with `code`, e.g. a small loader, instead of the compiled code.
+    /// The compiled code is then appended to the data cell as its last reference.
+    pub fn set_deploy_code(&mut self, code: Cell) {
+        self.deploy_code = Some(code);
+    }
+
+    /// Registers an already compiled method, e.g. a library reference cell, under the given id.
+    /// The cell is linked to the methods dictionary by reference instead of compiling a body.
+    pub fn add_external_method(&mut self, id: u32, code: Cell) {
+        self.external_methods.insert(id, code);
+    }
+
+    /// Overrides the contract version given by `.version` directive in the source.
+    pub fn set_version(&mut self, version: &str) {
+        self.version = Some(version.to_owned());
+    }
+
+    /// Disables appending the version cell to the code, e.g. to compare sizes of builds.
+    pub fn set_emit_version(&mut self, emit_version: bool) {
+        self.emit_version = emit_version;
+    }
+
     pub fn data(&self) -> Result<Cell> {
         let bytes =
             if let Some(ref pair) = self.keypair {
@@ -81,13 +436,16 @@ impl Program {
         // Persistent data feature is obsolete and should be removed.
         // Off-chain constructor should be used to create data layout instead.
         let (persistent_base, persistent_data) = self.engine.persistent_data();
-        let mut data_dict = HashmapE::with_hashmap(64, None);
+        let mut data_dict = HashmapE::with_hashmap(self.data_key_bits, None);
         if let Some(ref lang) = self.language {
             if lang == "C" || lang == "c" {
-                data_dict = HashmapE::with_hashmap(64, persistent_data)
+                if persistent_data.is_some() && self.data_key_bits != DATA_KEY_BITS {
+                    return Err(LinkerError::CompileError(format!("persistent data requires {}-bit data dictionary keys", DATA_KEY_BITS)).into());
+                }
+                data_dict = HashmapE::with_hashmap(self.data_key_bits, persistent_data)
             }
         }
-        let key = SliceData::load_builder(ptr_to_builder(persistent_base)?)?;
+        let key = data_dict_key(persistent_base, self.data_key_bits)?;
         let data = BuilderData::with_raw(bytes.to_vec(), PUBLIC_KEY_LENGTH * 8)?;
         data_dict.set(key, &SliceData::load_builder(data)?)
             .map_err(|e| format_err!("failed to pack pubkey to data dictionary: {}", e))?;
@@ -95,16 +453,29 @@ impl Program {
         builder
             .append_bit_one()?
             .checked_append_reference(data_dict.data().unwrap().clone())?;
+        if let Some(overflow) = &self.data_overflow {
+            builder.checked_append_reference(overflow.clone())?;
+        }
         builder.into_cell()
     }
 
+    /// Key of the data dictionary under which `data` stores the public key.
+    pub fn pubkey_slot(&self) -> i64 {
+        self.engine.persistent_data().0
+    }
+
     pub fn entry(&self) -> Lines {
         self.engine.entry()
     }
 
+    /// Functions defined in the program with their kinds, ids and source positions.
+    pub fn symbols(&self) -> Symbols {
+        self.engine.symbols()
+    }
+
     pub fn internal_method_dict(&mut self) -> Result<Option<Cell>> {
         let mut dict = self.prepare_methods(&self.engine.privates(), true)
-            .map_err(|(i, s)| format_err!("{}", s.replace("_name_", &self.engine.global_name(i).unwrap())))?;
+            .map_err(|(i, s)| LinkerError::CompileError(s.replace("_name_", &self.engine.global_name(i).unwrap())))?;
         self.dbgmap.append(&mut dict.1);
         Ok(dict.0.data().cloned())
     }
@@ -118,10 +489,10 @@ impl Program {
 
     pub fn public_method_dict(&mut self, remove_ctor: bool) -> Result<Option<Cell>> {
         let mut dict = self.prepare_methods(&self.engine.internals(), true)
-            .map_err(|(i, s)| format_err!("{}", s.replace("_name_", &self.engine.internal_name(i).unwrap())))?;
+            .map_err(|(i, s)| LinkerError::CompileError(s.replace("_name_", &self.engine.internal_name(i).unwrap())))?;
 
             self.insert_methods(&mut dict.0, &mut dict.1, &self.publics_filtered(remove_ctor), true)
-            .map_err(|(i, s)| format_err!("{}", s.replace("_name_", &self.engine.global_name(i).unwrap())))?;
+            .map_err(|(i, s)| LinkerError::CompileError(s.replace("_name_", &self.engine.global_name(i).unwrap())))?;
 
         self.dbgmap.append(&mut dict.1);
 
@@ -134,65 +505,211 @@ impl Program {
         out_file: Option<&str>,
         data_filename: Option<&str>,
     ) -> Result<String> {
-        let mut state_init = self.compile_to_state(self.print_code)?;
+        self.compile_to_file_for_workchains(&[wc], out_file, data_filename)
+    }
+
+    /// Same as `compile_to_file_ex`, but prints contract addresses for each of the given workchains.
+    pub fn compile_to_file_for_workchains(
+        &mut self,
+        workchains: &[i8],
+        out_file: Option<&str>,
+        data_filename: Option<&str>,
+    ) -> Result<String> {
         if self.print_code {
+            self.compile_to_state(true)?;
             return Ok("".to_string());
         }
-        if let Some(data_filename) = data_filename {
-            let data_cell = read_boc(std::fs::read(data_filename).unwrap()).unwrap().roots.remove(0);
-            state_init.set_data(data_cell);
-        }
-        let ret = save_to_file(state_init.clone(), out_file, wc, self.silent);
-        if out_file.is_some() && ret.is_ok() && !self.silent {
+        let state_init = self.compile_with_data(data_filename)?;
+        let start = Instant::now();
+        let ret = save_to_fs(&*self.fs, state_init.clone(), out_file, workchains, self.verbosity, self.boc_format);
+        self.print_elapsed("serialization", start);
+        if out_file.is_some() && ret.is_ok() && self.verbosity == Verbosity::Normal {
             println!("Contract successfully compiled. Saved to file {}.", out_file.unwrap());
-            println!("Contract initial hash: {:x}", state_init.hash().unwrap());
+            println!("Contract initial hash: {:x}", state_address(&state_init).unwrap());
+        }
+        if self.split_boc {
+            if let Ok(ref file_name) = ret {
+                let name = file_name.strip_suffix(".tvc").unwrap_or(file_name);
+                save_cell_to_file(&*self.fs, state_init.code.as_ref(), &format!("{}.code.boc", name), self.verbosity)?;
+                save_cell_to_file(&*self.fs, state_init.data.as_ref(), &format!("{}.data.boc", name), self.verbosity)?;
+            }
+        }
+        if self.stdout_hex && ret.is_ok() && self.verbosity == Verbosity::Normal {
+            let mut buffer = Vec::new();
+            self.boc_format.write_to(&state_init.serialize()?, &mut buffer)?;
+            println!("{}", hex::encode(buffer));
         }
         ret
     }
 
+    /// Compiles the contract like `compile_to_file_ex` and writes it to `w` in the configured format
+    /// instead of a file, e.g. to a pipe or an in-memory buffer. Nothing is printed.
+    pub fn write_boc(&mut self, w: &mut impl Write, data_filename: Option<&str>) -> Status {
+        let state_init = self.compile_with_data(data_filename)?;
+        self.boc_format.write_to(&state_init.serialize()?, w)
+    }
+
+    /// Compiles the contract like `write_boc` and returns the BOC as a hex string.
+    pub fn boc_hex(&mut self, data_filename: Option<&str>) -> Result<String> {
+        let mut buffer = Vec::new();
+        self.write_boc(&mut buffer, data_filename)?;
+        Ok(hex::encode(buffer))
+    }
+
+    /// Compiles the state and replaces or merges its data with the cell from `data_filename`.
+    fn compile_with_data(&mut self, data_filename: Option<&str>) -> Result<StateInit> {
+        let mut state_init = self.compile_to_state(false)?;
+        if let Some(data_filename) = data_filename {
+            let data_cell = load_cell_from_fs(&*self.fs, data_filename, "data")?;
+            if self.merge_data {
+                let data = state_init.data.clone().unwrap_or_default();
+                state_init.set_data(merge_data_dicts(data, data_cell, self.data_key_bits)
+                    .map_err(|e| format_err!("Failed to merge data from {}: {}", data_filename, e))?);
+            } else {
+                state_init.set_data(data_cell);
+            }
+        }
+        check_cell_depth(&state_init, self.max_cell_depth)?;
+        Ok(state_init)
+    }
+
+    /// Compiles the contract to a file like `compile_to_file_ex`, but prints nothing and returns
+    /// a summary: output file, code and data hashes, contract addresses and public method ids.
+    pub fn compile_to_file_json(
+        &mut self,
+        workchains: &[i8],
+        out_file: Option<&str>,
+        data_filename: Option<&str>,
+    ) -> Result<serde_json::Value> {
+        if self.print_code {
+            bail!("compilation summary is not available when only printing the code");
+        }
+        let verbosity = self.verbosity;
+        self.verbosity = Verbosity::Quiet;
+        let file_name = self.compile_to_file_for_workchains(workchains, out_file, data_filename);
+        self.verbosity = verbosity;
+        let file_name = file_name?;
+
+        let state = load_from_fs(&*self.fs, &file_name)?;
+        let address = state_address(&state)?;
+        let cell_hash = |cell: Option<&Cell>| cell.map(|cell| cell.repr_hash().to_hex_string());
+        let mut addresses = serde_json::Map::new();
+        for (wc, friendly) in friendly_addresses(address.as_slice(), workchains) {
+            addresses.insert(wc.to_string(), serde_json::to_value(friendly)?);
+        }
+        let mut methods = serde_json::Map::new();
+        for symbol in self.symbols() {
+            if symbol.kind == SymbolKind::Public {
+                methods.insert(symbol.name, serde_json::Value::from(symbol.id));
+            }
+        }
+        Ok(serde_json::json!({
+            "output_path": file_name,
+            "code_hash": cell_hash(state.code.as_ref()),
+            "data_hash": cell_hash(state.data.as_ref()),
+            "address_hash": format!("{:x}", address),
+            "addresses": addresses,
+            "methods": methods,
+            "size": state_size(&state, self.boc_format)?,
+        }))
+    }
+
+    /// Compiles the contract and measures its state the way it is saved to the TVC file.
+    pub fn size_report(&mut self) -> Result<SizeReport> {
+        if self.print_code {
+            bail!("size report is not available when only printing the code");
+        }
+        let state = self.compile_to_state(false)?;
+        state_size(&state, self.boc_format)
+    }
+
     fn compile_to_state(&mut self, only_print_code: bool) -> Result<StateInit> {
+        if self.check_abi {
+            self.check_abi()?;
+        }
         let mut state = StateInit::default();
-        let code = self.compile_asm(false)?;
+        let start = Instant::now();
+        let mut code = self.compile_asm(false)?;
+        if let Some(postprocessor) = &self.code_postprocessor {
+            code = postprocessor(code)
+                .map_err(|e| LinkerError::CompileError(format!("code postprocessing failed: {}", e)))?;
+        }
+        self.print_elapsed("code", start);
 
         if only_print_code {
             println!("{{\n  \"code\":\"{}\"\n}}", tree_of_cells_into_base64(Some(&code)));
             return Ok(state);
-        } else {
-            state.set_code(code);
         }
-        state.set_data(self.data()?);
+        let start = Instant::now();
+        let data = self.data()?;
+        match self.deploy_code.clone() {
+            Some(deploy_code) => {
+                let mut data = BuilderData::from_cell(&data)?;
+                data.checked_append_reference(code)?;
+                state.set_code(deploy_code);
+                state.set_data(data.into_cell()?);
+            }
+            None => {
+                state.set_code(code);
+                state.set_data(data);
+            }
+        }
+        self.print_elapsed("data", start);
         Ok(state)
     }
 
     fn compile_asm_old(&mut self, remove_ctor: bool) -> Result<Cell> {
+        if !self.external_methods.is_empty() {
+            return Err(LinkerError::CompileError("external methods are not supported with .selector".to_string()).into());
+        }
+        if self.code_salt.is_some() {
+            return Err(LinkerError::CompileError("code salt is not supported with .selector".to_string()).into());
+        }
         let internal_selector_text = vec![
             Line::new("DICTPUSHCONST 32\n", "<internal-selector>", 1),
             Line::new("DICTUGETJMP\n",      "<internal-selector>", 2),
         ];
-        let mut internal_selector = self.assemble(internal_selector_text)?;
+        let mut internal_selector = self.assemble_fixed("legacy-internal-selector", internal_selector_text)?;
         internal_selector.0.append_reference(SliceData::load_cell(self.internal_method_dict()?.unwrap_or_default())?);
 
         // adjust hash of internal_selector cell
         let hash = internal_selector.0.cell().repr_hash();
-        assert_eq!(internal_selector.1.len(), 1);
-        let entry = internal_selector.1.first_entry().unwrap();
+        if internal_selector.1.len() != 1 {
+            return Err(LinkerError::CompileError(format!("legacy internal selector: expected 1 debug info entry, got {}", internal_selector.1.len())).into());
+        }
+        let entry = internal_selector.1.first_entry()
+            .ok_or_else(|| LinkerError::CompileError("legacy internal selector: debug info entry not found".to_string()))?;
         self.dbgmap.insert(hash, entry.clone());
 
         let (mut main_selector, main_selector_dbg) = self.assemble(self.entry())?;
+        // The legacy .selector code takes its references by position: DICTPUSHCONST loads the
+        // public methods dictionary from the first reference and PUSHREFCONT loads the internal
+        // selector from the second one. DICTPUSHCONST can't push an empty dictionary, so an empty
+        // cell is appended in place of the missing dictionary to keep the layout intact.
         main_selector.append_reference(SliceData::load_cell(self.public_method_dict(remove_ctor)?.unwrap_or_default())?);
         main_selector.append_reference(internal_selector.0);
 
         // adjust hash of main_selector cell
         let hash = main_selector.cell().repr_hash();
-        assert_eq!(main_selector_dbg.len(), 1);
-        let entry = main_selector_dbg.first_entry().unwrap();
+        if main_selector_dbg.len() != 1 {
+            return Err(LinkerError::CompileError(format!("legacy main selector: expected 1 debug info entry, got {} (the .selector code must fit into a single cell)", main_selector_dbg.len())).into());
+        }
+        let entry = main_selector_dbg.first_entry()
+            .ok_or_else(|| LinkerError::CompileError("legacy main selector: debug info entry not found".to_string()))?;
         self.dbgmap.insert(hash, entry.clone());
 
         Ok(main_selector.cell().clone())
     }
 
     pub fn compile_asm(&mut self, remove_ctor: bool) -> Result<Cell> {
-        if !self.entry().is_empty() {
+        let legacy = match self.selector_kind {
+            SelectorKind::Auto => !self.entry().is_empty(),
+            SelectorKind::Legacy if self.entry().is_empty() =>
+                return Err(LinkerError::CompileError("legacy selector requires a .selector section in the sources".to_string()).into()),
+            SelectorKind::Legacy => true,
+            SelectorKind::Modern => false,
+        };
+        if legacy {
             // TODO wipe out the old behavior
             return self.compile_asm_old(remove_ctor);
         }
@@ -204,16 +721,30 @@ impl Program {
             Line::new("THROW 78\n",      "<internal-selector>", 3),
         ];
 
-        let mut internal_selector = self.assemble(internal_selector_text)?;
+        let mut internal_selector = self.assemble_fixed("internal-selector", internal_selector_text)?;
+
+        for name in self.engine.unused_privates() {
+            self.warn(format!("private function {} is not reachable from any public or internal function", name));
+        }
 
+        let start = Instant::now();
         let mut dict = self.prepare_methods(&self.engine.privates(), false)
-            .map_err(|(i, s)| format_err!("{}", s.replace("_name_", &self.engine.global_name(i).unwrap())))?;
+            .map_err(|(i, s)| LinkerError::CompileError(s.replace("_name_", &self.engine.global_name(i).unwrap())))?;
 
         self.insert_methods(&mut dict.0, &mut dict.1, &self.engine.internals(), false)
-            .map_err(|(i, s)| format_err!("{}", s.replace("_name_", &self.engine.internal_name(i).unwrap())))?;
+            .map_err(|(i, s)| LinkerError::CompileError(s.replace("_name_", &self.engine.internal_name(i).unwrap())))?;
 
         self.insert_methods(&mut dict.0, &mut dict.1, &self.publics_filtered(remove_ctor), false)
-            .map_err(|(i, s)| format_err!("{}", s.replace("_name_", &self.engine.global_name(i).unwrap())))?;
+            .map_err(|(i, s)| LinkerError::CompileError(s.replace("_name_", &self.engine.global_name(i).unwrap())))?;
+        for (id, code) in &self.external_methods {
+            let key = SliceData::load_cell(id.serialize()?)?;
+            if dict.0.get(key.clone())?.is_some() {
+                return Err(LinkerError::CompileError(format!("external method {} is already defined in the sources", id)).into());
+            }
+            dict.0.setref(key, code)
+                .map_err(|e| LinkerError::CompileError(format!("failed to set external method {} to dictionary: {}", id, e)))?;
+        }
+        self.print_elapsed("methods", start);
 
         let mut entry_points = vec![];
         for id in -2..1i32 {
@@ -225,16 +756,28 @@ impl Program {
         internal_selector.0.append_reference(SliceData::load_cell(dict.0.data().cloned().unwrap_or_default())?);
         self.dbgmap.append(&mut dict.1);
 
-        let version = self.engine.version();
-        if let Some(version) = version {
+        let version = match self.emit_version {
+            true => self.version.clone().or_else(|| self.engine.version()),
+            false => None,
+        };
+        if let Some(version) = &version {
             let version = version.as_bytes();
             internal_selector.0.append_reference(SliceData::from_raw(version.to_vec(), version.len() * 8));
         }
+        if let Some(salt) = self.code_salt.clone() {
+            if version.is_none() {
+                return Err(LinkerError::CompileError("code salt requires the contract version to be set".to_string()).into());
+            }
+            internal_selector.0.append_reference(SliceData::load_cell(salt)?);
+        }
 
         // adjust hash of internal_selector cell
         let hash = internal_selector.0.cell().repr_hash();
-        assert_eq!(internal_selector.1.len(), 1);
-        let entry = internal_selector.1.first_entry().unwrap();
+        if internal_selector.1.len() != 1 {
+            return Err(LinkerError::CompileError(format!("internal selector: expected 1 debug info entry, got {}", internal_selector.1.len())).into());
+        }
+        let entry = internal_selector.1.first_entry()
+            .ok_or_else(|| LinkerError::CompileError("internal selector: debug info entry not found".to_string()))?;
         self.dbgmap.insert(hash, entry.clone());
 
         let entry_selector_text = vec![
@@ -251,7 +794,10 @@ impl Program {
             Line::new("THROW 11\n",    "<entry-selector>", 11),
         ];
 
-        let mut entry_selector = self.assemble(entry_selector_text)?;
+        let mut entry_selector = match self.entry_selector.clone() {
+            Some(lines) => self.assemble(lines)?,
+            None => self.assemble_fixed("entry-selector", entry_selector_text)?,
+        };
 
         entry_selector.0.append_reference(internal_selector.0);
         entry_points.reverse();
@@ -261,8 +807,11 @@ impl Program {
 
         // adjust hash of entry_selector cell
         let hash = entry_selector.0.cell().repr_hash();
-        assert_eq!(entry_selector.1.len(), 1);
-        let entry = entry_selector.1.first_entry().unwrap();
+        if entry_selector.1.len() != 1 {
+            return Err(LinkerError::CompileError(format!("entry selector: expected 1 debug info entry, got {}", entry_selector.1.len())).into());
+        }
+        let entry = entry_selector.1.first_entry()
+            .ok_or_else(|| LinkerError::CompileError("entry selector: debug info entry not found".to_string()))?;
         self.dbgmap.insert(hash, entry.clone());
 
         let func_id = match self.engine.func_upgrade() {
@@ -291,73 +840,622 @@ impl Program {
             Line::new("POP C3\n",          "<func-upgrade-code>", 12),
             Line::new("CALL 2\n",          "<func-upgrade-code>", 13),
         ];
-        let mut func_upgrade_code = self.assemble(func_upgrade_text)?;
-        assert_eq!(func_upgrade_code.1.len(), 1);
+        let mut func_upgrade_code = self.assemble_fixed(&format!("func-upgrade-{}", func_id), func_upgrade_text)?;
+        if func_upgrade_code.1.len() != 1 {
+            return Err(LinkerError::CompileError(format!("func upgrade code: expected 1 debug info entry, got {}", func_upgrade_code.1.len())).into());
+        }
         let old_hash = func_upgrade_code.0.cell().repr_hash();
-        let entry = func_upgrade_code.1.get(&old_hash).unwrap();
+        let entry = func_upgrade_code.1.get(&old_hash)
+            .ok_or_else(|| LinkerError::CompileError(format!("func upgrade code: debug info entry for {:x} not found", old_hash)))?;
         func_upgrade_code.0.append_reference(entry_selector.0);
 
-        let hash = func_upgrade_code.0.cell().repr_hash();
-        self.dbgmap.insert(hash, entry.clone());
+        let hash = func_upgrade_code.0.cell().repr_hash();
+        self.dbgmap.insert(hash, entry.clone());
+
+        Ok(func_upgrade_code.0.cell().clone())
+    }
+
+    pub fn debug_print(&self) {
+        self.engine.debug_print();
+    }
+
+    /// Warnings reported while compiling the contract. The assembler itself reports only errors,
+    /// so these are the linker's own warnings, e.g. about unreachable private functions.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Prints the warning unless the output is muted and keeps it for `warnings`.
+    fn warn(&mut self, warning: String) {
+        if self.warnings.contains(&warning) {
+            return
+        }
+        if self.verbosity == Verbosity::Normal {
+            println!("Warning: {}", warning);
+        }
+        self.warnings.push(warning);
+    }
+
+    /// Compiles the code and prints its tree of cells.
+    pub fn debug_print_code(&mut self) -> Status {
+        let code = self.compile_asm(false)?;
+        print!("{}", cell_tree_printer(&code));
+        Ok(())
+    }
+
+    pub fn assemble(&mut self, lines: Lines) -> Result<(SliceData, DbgInfo)> {
+        let res = self.assembler.build(None, lines)
+            .map_err(|e| LinkerError::CompileError(format!("compilation failed: {}", e)))?
+            .finalize();
+        Ok(res)
+    }
+
+    /// Same as `assemble`, but the result is cached under `key` for the whole process.
+    /// Use it only for the code which doesn't depend on the program.
+    fn assemble_fixed(&mut self, key: &str, lines: Lines) -> Result<(SliceData, DbgInfo)> {
+        if let Some(res) = FIXED_CODE.lock().unwrap().get(key) {
+            return Ok(res.clone())
+        }
+        let res = self.assemble(lines)?;
+        FIXED_CODE.lock().unwrap().insert(key.to_string(), res.clone());
+        Ok(res)
+    }
+}
+
+/// Checks that code and data cell trees of the state are not deeper than `max_depth`.
+pub fn check_cell_depth(state: &StateInit, max_depth: u16) -> Status {
+    for (name, cell) in [("code", &state.code), ("data", &state.data)] {
+        if let Some(cell) = cell {
+            let depth = cell.repr_depth();
+            if depth > max_depth {
+                return Err(LinkerError::LimitExceeded(format!("{} cell depth {} exceeds the limit of {}", name, depth, max_depth)).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn save_to_file(state: StateInit, name: Option<&str>, wc: i8, silent: bool) -> Result<String> {
+    save_to_file_ex(state, name, &[wc], if silent { Verbosity::Silent } else { Verbosity::Normal }, SerializationFormat::Default)
+}
+
+/// Saves the state to `name` or to `<address>.tvc` if the name is not given and returns the file name.
+/// The `.tvc` extension is added to the name if it has no extension.
+/// Contract addresses are printed for each of the given workchains.
+pub fn save_to_file_ex(
+    state: StateInit,
+    name: Option<&str>,
+    workchains: &[i8],
+    verbosity: Verbosity,
+    format: SerializationFormat,
+) -> Result<String> {
+    save_to_fs(&NativeFs, state, name, workchains, verbosity, format)
+}
+
+/// Same as `save_to_file_ex`, but the file is written to the given file system.
+pub fn save_to_fs(
+    fs: &dyn Fs,
+    state: StateInit,
+    name: Option<&str>,
+    workchains: &[i8],
+    verbosity: Verbosity,
+    format: SerializationFormat,
+) -> Result<String> {
+    let root = state.serialize()?;
+
+    let mut print_filename = false;
+    let address = state_address(&state)?;
+    let file_name = if let Some(name) = name {
+        if std::path::Path::new(name).extension().is_none() {
+            format!("{}.tvc", name)
+        } else {
+            name.to_string()
+        }
+    } else {
+        print_filename = true;
+        format!("{:x}.tvc", address)
+    };
+
+    fs.write_with(&file_name, &mut |dest: &mut dyn Write| format.write_to(&root, dest))?;
+
+    if print_filename {
+        if verbosity == Verbosity::Silent {
+            println!("{{\n  \"output_path\":\"{}\"\n}}", &file_name);
+        } else if verbosity == Verbosity::Normal {
+            println!("Saved contract to file {}", &file_name);
+            for (wc, friendly) in friendly_addresses(address.as_slice(), workchains) {
+                if workchains.len() > 1 {
+                    println!("workchain {}:", wc);
+                }
+                println!("testnet:");
+                println!("Non-bounceable address (for init): {}", &friendly.testnet_non_bounceable);
+                println!("Bounceable address (for later access): {}", &friendly.testnet_bounceable);
+                println!("mainnet:");
+                println!("Non-bounceable address (for init): {}", &friendly.mainnet_non_bounceable);
+                println!("Bounceable address (for later access): {}", &friendly.mainnet_bounceable);
+                println!("Deploy link: {}", ton_transfer_uri(&friendly, None));
+            }
+        }
+    }
+    Ok(file_name)
+}
+
+/// Loads the root cell of a binary or base64-encoded BOC file, `kind` is used in error messages.
+pub fn load_cell_file(filename: &str, kind: &str) -> Result<Cell> {
+    load_cell_from_fs(&NativeFs, filename, kind)
+}
+
+fn load_cell_from_fs(fs: &dyn Fs, filename: &str, kind: &str) -> Result<Cell> {
+    let bytes = fs.read(filename)
+        .map_err(|e| LinkerError::IoError(format!("Failed to read {} file {}: {}", kind, filename, e)))?;
+    let bytes = match read_boc(&bytes) {
+        Ok(_) => bytes,
+        Err(_) => base64::decode(String::from_utf8_lossy(&bytes).trim())
+            .map_err(|_| LinkerError::InvalidBoc(format!("File {} contains neither binary nor base64-encoded BOC", filename)))?,
+    };
+    read_boc(bytes)
+        .and_then(|boc| boc.withdraw_single_root())
+        .map_err(|e| LinkerError::InvalidBoc(format!("Failed to load {} from {}: {}", kind, filename, e)).into())
+}
+
+/// Creates a library reference cell pointing to the library cell with the given hash.
+pub fn library_cell(hash: &UInt256) -> Result<Cell> {
+    let mut builder = BuilderData::with_raw(vec![2], 8)?; // library reference tag
+    builder.append_raw(hash.as_slice(), 256)?;
+    builder.set_type(CellType::LibraryReference);
+    builder.into_cell()
+}
+
+fn load_data_dict(data: Cell, key_bits: usize) -> Result<HashmapE> {
+    let mut slice = SliceData::load_cell(data)?;
+    let root = if slice.get_next_bit()? { Some(slice.checked_drain_reference()?) } else { None };
+    Ok(HashmapE::with_hashmap(key_bits, root))
+}
+
+/// Key of the data dictionary for the slot, the slot is zero-extended to keys wider than 64 bits.
+fn data_dict_key(slot: i64, key_bits: usize) -> Result<SliceData> {
+    if key_bits == 0 || key_bits > 1023 {
+        return Err(LinkerError::CompileError(format!("invalid data dictionary key width {}", key_bits)).into());
+    }
+    let mut builder = BuilderData::new();
+    if key_bits >= 64 {
+        builder.append_raw(&vec![0; (key_bits - 64 + 7) / 8], key_bits - 64)?;
+        builder.append_builder(&ptr_to_builder(slot)?)?;
+    } else if slot >= 0 && slot >> key_bits == 0 {
+        builder.append_bits(slot as usize, key_bits)?;
+    } else {
+        return Err(LinkerError::CompileError(format!("slot {} doesn't fit into {}-bit data dictionary key", slot, key_bits)).into());
+    }
+    SliceData::load_builder(builder)
+}
+
+/// Reads the public key stored by `Program::data` from the data cell, the default slot 0 is used.
+pub fn extract_pubkey(data: &Cell) -> Result<[u8; PUBLIC_KEY_LENGTH]> {
+    extract_pubkey_ex(data, 0, DATA_KEY_BITS)
+}
+
+/// Same as `extract_pubkey`, but the key is read from the given slot of a data dictionary
+/// with `key_bits` wide keys, see `Program::pubkey_slot`.
+pub fn extract_pubkey_ex(data: &Cell, slot: i64, key_bits: usize) -> Result<[u8; PUBLIC_KEY_LENGTH]> {
+    let key = data_dict_key(slot, key_bits)?;
+    let mut value = load_data_dict(data.clone(), key_bits)?.get(key)?
+        .ok_or_else(|| LinkerError::InvalidBoc(format!("data dictionary has no public key at slot {}", slot)))?;
+    if value.remaining_bits() < PUBLIC_KEY_LENGTH * 8 {
+        return Err(LinkerError::InvalidBoc(format!("value at slot {} is too short for a public key", slot)).into())
+    }
+    let mut pubkey = [0u8; PUBLIC_KEY_LENGTH];
+    pubkey.copy_from_slice(&value.get_next_bytes(PUBLIC_KEY_LENGTH)?);
+    Ok(pubkey)
+}
+
+/// Unites data dictionaries of two data cells, fails if a key is present in both of them.
+fn merge_data_dicts(data: Cell, other: Cell, key_bits: usize) -> Result<Cell> {
+    // references following the dictionary, e.g. the code appended by `set_deploy_code`, are kept
+    let mut rest = SliceData::load_cell(data.clone())?;
+    if rest.get_next_bit()? {
+        rest.checked_drain_reference()?;
+    }
+    let mut data_dict = load_data_dict(data, key_bits)?;
+    load_data_dict(other, key_bits)?.iterate_slices(|key, value| {
+        if data_dict.get(key.clone())?.is_some() {
+            bail!("key {} is already present in data", key.to_hex_string());
+        }
+        data_dict.set(key, &value)?;
+        Ok(true)
+    })?;
+    let mut builder = BuilderData::new();
+    match data_dict.data() {
+        Some(root) => builder.append_bit_one()?.checked_append_reference(root.clone())?,
+        None => builder.append_bit_zero()?,
+    };
+    while rest.remaining_references() > 0 {
+        builder.checked_append_reference(rest.checked_drain_reference()?)?;
+    }
+    builder.into_cell()
+}
+
+fn save_cell_to_file(fs: &dyn Fs, cell: Option<&Cell>, name: &str, verbosity: Verbosity) -> Status {
+    let cell = cell.ok_or_else(|| LinkerError::InvalidBoc(format!("Failed to save {}: cell is empty", name)))?;
+    fs.write(name, &write_boc(cell)?)
+        .map_err(|e| LinkerError::IoError(format!("Failed to save {}: {}", name, e)))?;
+    if verbosity == Verbosity::Normal {
+        println!("Saved cell to file {}", name);
+    }
+    Ok(())
+}
+
+/// Compiles the sources and checks that the code cell hash is equal to the code hash of the TVC file.
+/// If the hashes differ, the first differing cell is returned as a path of reference indexes from the root.
+pub fn verify_against(sources: Vec<&std::path::Path>, abi_json: Option<String>, tvc_path: &str) -> Result<Option<String>> {
+    let mut prog = Program::new(ParseEngine::new(sources, abi_json)?)?;
+    let code = prog.compile_asm(false)?;
+    let state = load_from_file(tvc_path)?;
+    let expected = state.code.ok_or_else(|| LinkerError::InvalidBoc(format!("{} doesn't contain code", tvc_path)))?;
+    Ok(first_difference(&expected, &code, "root".to_string()))
+}
+
+/// Contract to compile with `compile_batch`.
+#[derive(Clone, Debug)]
+pub struct CompileJob {
+    /// Libraries followed by the contract source.
+    pub sources: Vec<std::path::PathBuf>,
+    pub abi_json: Option<String>,
+    /// BOC file with the data cell replacing the compiled one, e.g. with constructor parameters.
+    pub data_file: Option<String>,
+    /// Values of the ABI data variables in JSON, e.g. the static variables read by the constructor.
+    /// They are written to the data after `data_file` is applied, `abi_json` is required.
+    pub ctor_params: Option<String>,
+    /// Output file, `<address>.tvc` if not given.
+    pub out_file: Option<String>,
+    /// Workchain of the contract address, -1 by default as in the command line.
+    pub wc: i8,
+}
+
+impl Default for CompileJob {
+    fn default() -> Self {
+        CompileJob {
+            sources: Vec::new(),
+            abi_json: None,
+            data_file: None,
+            ctor_params: None,
+            out_file: None,
+            wc: -1,
+        }
+    }
+}
+
+/// Results of a successfully compiled `CompileJob`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompileSummary {
+    pub output_path: String,
+    pub address: UInt256,
+    pub code_hash: UInt256,
+    pub size: SizeReport,
+}
+
+/// Compiles the contracts one by one in the current process without spawning the linker for each
+/// of them. Every job parses and assembles its own sources including the libraries, only the fixed
+/// selector snippets are shared. Nothing is printed, a failed job doesn't stop the others.
+pub fn compile_batch(jobs: Vec<CompileJob>) -> Vec<Result<CompileSummary>> {
+    jobs.into_iter().map(|job| compile_job(job).map(|(summary, _, _)| summary)).collect()
+}
+
+/// Compiles the contract and describes the build with a manifest, which allows to reproduce it.
+pub fn compile_with_manifest(job: CompileJob) -> Result<(CompileSummary, Manifest)> {
+    let sources = job.sources.clone();
+    let abi_json = job.abi_json.clone();
+    let data_file = job.data_file.clone();
+    let (summary, state, fs) = compile_job(job)?;
+    let sources = sources.iter().map(|path| path.as_path()).collect::<Vec<_>>();
+    let manifest = build_manifest_from_fs(&*fs, &sources, abi_json.as_deref(), data_file.as_deref(), &state)?;
+    Ok((summary, manifest))
+}
+
+fn compile_job(job: CompileJob) -> Result<(CompileSummary, StateInit, Box<dyn Fs>)> {
+    let sources = job.sources.iter().map(|path| path.as_path()).collect();
+    let mut prog = Program::new(ParseEngine::new(sources, job.abi_json.clone())?)?;
+    prog.set_verbosity(Verbosity::Quiet);
+    let mut state = prog.compile_with_data(job.data_file.as_deref())?;
+    if let Some(params) = job.ctor_params.as_deref() {
+        let abi_json = job.abi_json.as_deref()
+            .ok_or_else(|| format_err!("constructor params can't be applied without the ABI"))?;
+        let data = SliceData::load_cell(state.data.clone().unwrap_or_default())?;
+        state.set_data(update_contract_data(abi_json, params, data)?.into_cell());
+    }
+    let output_path = save_to_fs(&*prog.fs, state.clone(), job.out_file.as_deref(), &[job.wc], prog.verbosity, prog.boc_format)?;
+    let summary = CompileSummary {
+        address: state_address(&state)?,
+        code_hash: state.code.as_ref().map(|code| code.repr_hash()).unwrap_or_default(),
+        size: state_size(&state, prog.boc_format)?,
+        output_path,
+    };
+    Ok((summary, state, prog.fs))
+}
+
+/// Input file of a build with its sha256 hash.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ManifestInput {
+    pub path: String,
+    pub sha256: String,
+}
+
+/// Inputs and results of a build. Source files are listed in the order they are given to the linker.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Manifest {
+    pub linker_version: String,
+    pub sources: Vec<ManifestInput>,
+    pub abi_sha256: Option<String>,
+    /// Data cell file replacing the compiled data, e.g. with constructor parameters.
+    pub data_file: Option<ManifestInput>,
+    pub code_hash: Option<String>,
+    pub data_hash: Option<String>,
+}
+
+impl Manifest {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(<sha2::Sha256 as sha2::Digest>::digest(data))
+}
+
+/// Describes the build of `state` from the given inputs.
+pub fn build_manifest(sources: &[&std::path::Path], abi_json: Option<&str>, data_file: Option<&str>, state: &StateInit) -> Result<Manifest> {
+    build_manifest_from_fs(&NativeFs, sources, abi_json, data_file, state)
+}
+
+/// Describes the build of `state` reading the input files from `fs`.
+pub fn build_manifest_from_fs(
+    fs: &dyn Fs,
+    sources: &[&std::path::Path],
+    abi_json: Option<&str>,
+    data_file: Option<&str>,
+    state: &StateInit,
+) -> Result<Manifest> {
+    let input = |path: &str| -> Result<ManifestInput> {
+        let data = fs.read(path)
+            .map_err(|e| LinkerError::IoError(format!("failed to read file {}: {}", path, e)))?;
+        Ok(ManifestInput { path: path.to_string(), sha256: sha256_hex(&data) })
+    };
+    let mut inputs = Vec::new();
+    for path in sources {
+        let path = path.to_str()
+            .ok_or_else(|| LinkerError::IoError(format!("invalid source path {}", path.display())))?;
+        if path == crate::parser::STDIN_PATH {
+            return Err(LinkerError::IoError("manifest can't describe sources read from the standard input".to_string()).into());
+        }
+        inputs.push(input(path)?);
+    }
+    let cell_hash = |cell: Option<&Cell>| cell.map(|cell| cell.repr_hash().to_hex_string());
+    Ok(Manifest {
+        linker_version: env!("CARGO_PKG_VERSION").to_string(),
+        sources: inputs,
+        abi_sha256: abi_json.map(|abi| sha256_hex(abi.as_bytes())),
+        data_file: data_file.map(input).transpose()?,
+        code_hash: cell_hash(state.code.as_ref()),
+        data_hash: cell_hash(state.data.as_ref()),
+    })
+}
+
+fn first_difference(expected: &Cell, actual: &Cell, path: String) -> Option<String> {
+    if expected.repr_hash() == actual.repr_hash() {
+        return None;
+    }
+    if expected.bit_length() == actual.bit_length() && expected.data() == actual.data() &&
+        expected.references_count() == actual.references_count() {
+        for i in 0..expected.references_count() {
+            let (expected, actual) = match (expected.reference(i), actual.reference(i)) {
+                (Ok(expected), Ok(actual)) => (expected, actual),
+                _ => break,
+            };
+            if let Some(path) = first_difference(&expected, &actual, format!("{}.{}", path, i)) {
+                return Some(path);
+            }
+        }
+    }
+    Some(path)
+}
+
+/// Size of a contract state: storage fees are charged for its unique cells and bits.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SizeReport {
+    /// Length of the serialized bag of cells.
+    pub boc_bytes: usize,
+    pub cells: usize,
+    pub bits: usize,
+}
+
+/// Measures the state serialized with the given format, each cell is counted once.
+pub fn state_size(state: &StateInit, format: SerializationFormat) -> Result<SizeReport> {
+    let root = state.serialize()?;
+    let mut report = SizeReport { boc_bytes: format.serialize(&root)?.len(), ..Default::default() };
+    let mut seen = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(cell) = stack.pop() {
+        if !seen.insert(cell.repr_hash()) {
+            continue
+        }
+        report.cells += 1;
+        report.bits += cell.bit_length();
+        for i in 0..cell.references_count() {
+            stack.push(cell.reference(i)?);
+        }
+    }
+    Ok(report)
+}
+
+/// Differences between two contract states, e.g. before and after a code upgrade.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StateDiff {
+    pub code_changed: bool,
+    pub data_changed: bool,
+    pub split_depth_changed: bool,
+    pub special_changed: bool,
+    pub library_changed: bool,
+    /// Path of reference indexes to the first differing code cell, e.g. `root.0.1`.
+    pub code_difference: Option<String>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+}
 
-        Ok(func_upgrade_code.0.cell().clone())
+pub fn diff_state_init(old: &StateInit, new: &StateInit) -> StateDiff {
+    let hash = |cell: Option<&Cell>| cell.map(|cell| cell.repr_hash());
+    let code_difference = match (&old.code, &new.code) {
+        (Some(old), Some(new)) => first_difference(old, new, "root".to_string()),
+        (None, None) => None,
+        _ => Some("root".to_string()),
+    };
+    StateDiff {
+        code_changed: hash(old.code.as_ref()) != hash(new.code.as_ref()),
+        data_changed: hash(old.data.as_ref()) != hash(new.data.as_ref()),
+        split_depth_changed: old.split_depth != new.split_depth,
+        special_changed: old.special != new.special,
+        library_changed: hash(old.library.root()) != hash(new.library.root()),
+        code_difference,
     }
+}
 
-    pub fn debug_print(&self) {
-        self.engine.debug_print();
+/// Loads two TVC files and compares their states.
+pub fn diff_tvc_files(old_path: &str, new_path: &str) -> Result<StateDiff> {
+    Ok(diff_state_init(&load_from_file(old_path)?, &load_from_file(new_path)?))
+}
+
+/// Writes the account state to a BOC file, e.g. after applying the results of a test call.
+pub fn save_account_to_file(account: &Account, name: &str) -> Status {
+    let buffer = account.write_to_bytes()?;
+    let mut file = File::create(name)
+        .map_err(|e| LinkerError::IoError(format!("failed to create file {}: {}", name, e)))?;
+    file.write_all(&buffer)?;
+    Ok(())
+}
+
+/// Replaces code and data of the account with ones from the post-execution state.
+pub fn update_account_state(account: &mut Account, state: &StateInit) -> Status {
+    if account.state_init().is_none() {
+        bail!("Account doesn't contain stateInit.")
+    }
+    if let Some(code) = state.code.clone() {
+        account.set_code(code);
+    }
+    if let Some(data) = state.data.clone() {
+        account.set_data(data);
     }
+    Ok(())
+}
 
-    pub fn assemble(&mut self, lines: Lines) -> Result<(SliceData, DbgInfo)> {
-        let res = self.assembler.build(None, lines)
-            .map_err(|e| format_err!("compilation failed: {}", e))?
-            .finalize();
-        Ok(res)
+/// User-friendly forms of a contract address in one workchain.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FriendlyAddresses {
+    pub testnet_non_bounceable: String,
+    pub testnet_bounceable: String,
+    pub mainnet_non_bounceable: String,
+    pub mainnet_bounceable: String,
+}
+
+impl FriendlyAddresses {
+    pub fn new(wc: i8, addr: &[u8]) -> Self {
+        FriendlyAddresses {
+            testnet_non_bounceable: calc_userfriendly_address(wc, addr, false, true),
+            testnet_bounceable: calc_userfriendly_address(wc, addr, true, true),
+            mainnet_non_bounceable: calc_userfriendly_address(wc, addr, false, false),
+            mainnet_bounceable: calc_userfriendly_address(wc, addr, true, false),
+        }
     }
 }
 
-pub fn save_to_file(state: StateInit, name: Option<&str>, wc: i8, silent: bool) -> Result<String> {
-    let buffer = state.write_to_bytes()?;
+/// Builds a `ton://transfer/` deep link to the mainnet non-bounceable address, e.g. to send the initial
+/// balance for the deployment from a wallet. The address is encoded in URL-safe base64.
+pub fn ton_transfer_uri(addr: &FriendlyAddresses, amount: Option<u128>) -> String {
+    let address = addr.mainnet_non_bounceable.replace('+', "-").replace('/', "_");
+    match amount {
+        Some(amount) => format!("ton://transfer/{}?amount={}", address, amount),
+        None => format!("ton://transfer/{}", address),
+    }
+}
 
-    let mut print_filename = false;
-    let address = state.hash().unwrap();
-    let file_name = if let Some(name) = name {
-        name.to_string()
-    } else {
-        print_filename = true;
-        format!("{:x}.tvc", address)
-    };
+/// Calculates user-friendly addresses of the account hash `addr` for each of the workchains.
+pub fn friendly_addresses(addr: &[u8], workchains: &[i8]) -> BTreeMap<i8, FriendlyAddresses> {
+    workchains.iter().map(|&wc| (wc, FriendlyAddresses::new(wc, addr))).collect()
+}
 
-    let mut file = File::create(&file_name)?;
-    file.write_all(&buffer)?;
+/// Calculates the address of the contract, which is the hash of its state.
+/// The state may contain pruned branch cells, e.g. if it is taken from a Merkle proof: the address is
+/// the level 0 hash of the state cell, which for pruned branches is the hash of the original subtree.
+pub fn state_address(state: &StateInit) -> Result<UInt256> {
+    Ok(state.serialize()?.hash(0))
+}
 
-    if print_filename {
-        if silent {
-            println!("{{\n  \"output_path\":\"{}\"\n}}", &file_name);
-        } else {
-            println!("Saved contract to file {}", &file_name);
-            println!("testnet:");
-            println!("Non-bounceable address (for init): {}", &calc_userfriendly_address(wc, address.as_slice(), false, true));
-            println!("Bounceable address (for later access): {}", &calc_userfriendly_address(wc, address.as_slice(), true, true));
-            println!("mainnet:");
-            println!("Non-bounceable address (for init): {}", &calc_userfriendly_address(wc, address.as_slice(), false, false));
-            println!("Bounceable address (for later access): {}", &calc_userfriendly_address(wc, address.as_slice(), true, false));
-        }
+/// Recomputes the address of the state, e.g. after its data is replaced, and its user-friendly forms in `wc`.
+pub fn recompute_address(state: &StateInit, wc: i8) -> Result<(UInt256, FriendlyAddresses)> {
+    let address = state_address(state)?;
+    let friendly = FriendlyAddresses::new(wc, address.as_slice());
+    Ok((address, friendly))
+}
+
+/// Builds the state of a contract from an already compiled code cell, e.g. by another toolchain,
+/// bypassing the parsing and assembling. Returns the state with its addresses in the given workchains.
+pub fn build_state_init(code: Cell, data: Option<Cell>, workchains: &[i8]) -> Result<(StateInit, BTreeMap<i8, FriendlyAddresses>)> {
+    let mut state = StateInit::default();
+    state.set_code(code);
+    if let Some(data) = data {
+        state.set_data(data);
     }
-    Ok(file_name)
+    let address = state_address(&state)?;
+    let addresses = friendly_addresses(address.as_slice(), workchains);
+    Ok((state, addresses))
+}
+
+/// Calculates the address the contract with the given code and data would be deployed to,
+/// e.g. with the data derived from a salt or an owner key before the contract is deployed.
+pub fn counterfactual_address(code: &Cell, data: &Cell, wc: i8) -> Result<(UInt256, FriendlyAddresses)> {
+    let mut state = StateInit::default();
+    state.set_code(code.clone());
+    state.set_data(data.clone());
+    recompute_address(&state, wc)
+}
+
+/// Loads the contract from a tvc file and returns user-friendly forms of its address in `wc`.
+pub fn friendly_addresses_of_file(contract_file: &str, wc: i8) -> Result<FriendlyAddresses> {
+    Ok(recompute_address(&load_from_file(contract_file)?, wc)?.1)
 }
 
 fn calc_userfriendly_address(wc: i8, addr: &[u8], bounce: bool, testnet: bool) -> String {
+    calc_userfriendly_address_with_crc(wc, addr, bounce, testnet, &XMODEM)
+}
+
+/// Encodes a user-friendly address using the given CRC16 algorithm for the checksum.
+/// TON networks use CRC-16/XMODEM, other algorithms are meant for interoperability with forks.
+pub fn calc_userfriendly_address_with_crc(wc: i8, addr: &[u8], bounce: bool, testnet: bool, crc: &crc::Crc<u16>) -> String {
     let mut bytes: Vec<u8> = vec![];
     bytes.push(if bounce { 0x11 } else { 0x51 } + if testnet { 0x80 } else { 0 });
     bytes.push(wc as u8);
     bytes.extend_from_slice(addr);
-    let crc = XMODEM.checksum(&bytes);
+    let crc = crc.checksum(&bytes);
     bytes.extend_from_slice(&crc.to_be_bytes());
     encode(&bytes)
 }
 
+fn single_root(file_name: &str, mut roots: Vec<Cell>) -> Result<Cell> {
+    match roots.len() {
+        0 => Err(LinkerError::InvalidBoc(format!("{} contains no cells", file_name)).into()),
+        1 => Ok(roots.remove(0)),
+        n => Err(LinkerError::InvalidBoc(format!("{} contains {} root cells, expected only StateInit", file_name, n)).into()),
+    }
+}
+
 pub fn load_from_file(contract_file: &str) -> Result<StateInit> {
-    let mut cell = read_boc(std::fs::read(contract_file)?)?.roots.remove(0);
+    load_from_fs(&NativeFs, contract_file)
+}
+
+/// Same as `load_from_file`, but the file is read from the given file system.
+pub fn load_from_fs(fs: &dyn Fs, contract_file: &str) -> Result<StateInit> {
+    let mut cell = single_root(contract_file, read_boc(fs.read(contract_file)?)?.roots)?;
     // try appending a dummy library cell if there is no such cell in the tvc file
     if cell.references_count() == 2 {
         let mut adjusted_cell = BuilderData::from_cell(&cell)?;
@@ -367,12 +1465,78 @@ pub fn load_from_file(contract_file: &str) -> Result<StateInit> {
     StateInit::construct_from_cell(cell)
 }
 
+/// Lists ids of the methods present in the code. For the code compiled without `.selector` these are
+/// the entry points (0, -1 and -2), which are referenced by the entry selector, and the ids of the internal
+/// selector's dictionary, i.e. internal, public and private functions. The entry selector can be wrapped
+/// into the code of `.pragma selector-func-solidity` or `.pragma selector-old-sol`. For the code compiled
+/// with `.selector` these are the ids of the public and the internal methods dictionaries.
+pub fn code_method_ids(code: &Cell) -> Result<Vec<i32>> {
+    let is_empty = |cell: &Cell| cell.bit_length() == 0 && cell.references_count() == 0;
+    let reference = |cell: &Cell, i: usize, name: &str| cell.reference(i)
+        .map_err(|e| LinkerError::InvalidBoc(format!("{} is not found: {}", name, e)));
+    let mut ids = Vec::new();
+    let mut push_keys = |dict: Cell| -> Status {
+        if !is_empty(&dict) {
+            HashmapE::with_hashmap(32, Some(dict)).iterate_slices(|mut key, _| {
+                ids.push(key.get_next_i32()?);
+                Ok(true)
+            })?;
+        }
+        Ok(())
+    };
+    match code.references_count() {
+        // the func upgrade code referencing the entry selector
+        1 => {
+            let entry_selector = reference(code, 0, "entry selector")?;
+            if entry_selector.references_count() != 4 {
+                return Err(LinkerError::InvalidBoc("unknown code layout: the func upgrade code doesn't reference the entry selector".to_string()).into());
+            }
+            return code_method_ids(&entry_selector)
+        }
+        // the legacy .selector code referencing the public methods dictionary and the internal selector
+        2 => {
+            push_keys(reference(code, 0, "public methods dictionary")?)?;
+            let internal_selector = reference(code, 1, "internal selector")?;
+            push_keys(reference(&internal_selector, 0, "internal methods dictionary")?)?;
+        }
+        // the entry selector referencing the internal selector and the entry points
+        4 => {
+            let internal_selector = reference(code, 0, "internal selector")?;
+            push_keys(reference(&internal_selector, 0, "methods dictionary")?)?;
+            for (i, id) in [0, -1, -2].into_iter().enumerate() {
+                if !is_empty(&reference(code, i + 1, "entry point")?) {
+                    ids.push(id);
+                }
+            }
+        }
+        n => return Err(LinkerError::InvalidBoc(format!("unknown code layout: the root cell has {} references", n)).into()),
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Same as `code_method_ids` for the code of the contract file.
+pub fn load_method_ids(contract_file: &str) -> Result<Vec<i32>> {
+    let state = load_from_file(contract_file)?;
+    let code = state.code.ok_or_else(|| LinkerError::InvalidBoc(format!("{} contains no code", contract_file)))?;
+    code_method_ids(&code)
+}
+
+/// Loads a message serialized to a BOC file, e.g. one captured from the network.
+pub fn load_message(file_name: &str) -> Result<Message> {
+    let bytes = std::fs::read(file_name)
+        .map_err(|e| LinkerError::IoError(format!("failed to read file {}: {}", file_name, e)))?;
+    let root = single_root(file_name, read_boc(bytes)?.roots)?;
+    Message::construct_from_cell(root)
+        .map_err(|e| LinkerError::InvalidBoc(format!("{} doesn't contain a message: {}", file_name, e)).into())
+}
+
 pub fn load_stateinit(file_name: &str) -> Result<(SliceData, Vec<u8>)> {
     let mut orig_bytes = Vec::new();
     let mut f = File::open(file_name)?;
     f.read_to_end(&mut orig_bytes)?;
 
-    let mut root = read_boc(orig_bytes.clone())?.roots.remove(0);
+    let mut root = single_root(file_name, read_boc(orig_bytes.clone())?.roots)?;
     if root.references_count() == 2 { // append empty library cell
         let mut adjusted_cell = BuilderData::from_cell(&root)?;
         adjusted_cell.checked_append_reference(Cell::default())?;
@@ -389,8 +1553,8 @@ pub fn get_now() -> u32 {
 mod tests {
     use crate::abi;
     use crate::testcall::{load_config, load_debug_info, call_contract, MsgInfo, TestCallParams};
-    use crate::{printer::get_version_mycode_aware, program::load_stateinit};
-    use crate::testcall::TraceLevel;
+    use crate::{printer::{get_code_salt, get_version_mycode_aware, try_get_version}, program::load_stateinit};
+    use crate::testcall::{GasLimit, TraceLevel, VmVersion};
     use super::*;
 
     use std::{fs::File, str::FromStr};
@@ -437,11 +1601,17 @@ mod tests {
                 config: config_cell,
                 key_file,
                 ticktock,
-                gas_limit,
+                gas_limit: gas_limit.map(GasLimit::Limit),
                 action_decoder,
                 trace_level,
                 debug_info,
+                trace_callback: None,
                 capabilities: 0x42E, // default
+                // pinned, so that the out of gas threshold of test_call_with_gas_limit is stable
+                vm_version: Some(VmVersion::Initial),
+                block_time_override: None,
+                trace_filter: None,
+                global_id: None,
             }
         )?;
         if is_vm_success {
@@ -495,6 +1665,156 @@ mod tests {
         assert_eq!(addr, "kf/8uRo6OBbQ97jCx2EIuKm8Wmt6Vb15+KsQHFLbKSMiYIny");
     }
 
+    #[test]
+    fn test_address_crc() {
+        let addr = hex::decode("fcb91a3a3816d0f7b8c2c76108b8a9bc5a6b7a55bd79f8ab101c52db29232260").unwrap();
+        assert_eq!(
+            calc_userfriendly_address_with_crc(-1, &addr, true, true, &XMODEM),
+            calc_userfriendly_address(-1, &addr, true, true));
+        let arc = crc::Crc::<u16>::new(&crc::CRC_16_ARC);
+        let other = calc_userfriendly_address_with_crc(-1, &addr, true, true, &arc);
+        assert_ne!(other, calc_userfriendly_address(-1, &addr, true, true));
+        assert_eq!(other.len(), 48);
+    }
+
+    #[test]
+    fn test_serialization_format() {
+        let state = load_from_file("tests/data.tvc").unwrap();
+        let root = state.serialize().unwrap();
+        assert_eq!(SerializationFormat::Default.serialize(&root).unwrap(), state.write_to_bytes().unwrap());
+        for format in ["crc", "index_crc"] {
+            let bytes = SerializationFormat::from_str(format).unwrap().serialize(&root).unwrap();
+            assert_eq!(read_boc(bytes).unwrap().withdraw_single_root().unwrap(), root);
+        }
+        assert_eq!(
+            SerializationFormat::from_str("fift").unwrap().serialize(&root).unwrap(),
+            SerializationFormat::IndexCrc.serialize(&root).unwrap()
+        );
+        assert!(SerializationFormat::from_str("unknown").is_err());
+        for format in [SerializationFormat::Default, SerializationFormat::Crc, SerializationFormat::IndexCrc, SerializationFormat::Fift] {
+            let mut streamed = Vec::new();
+            format.write_to(&root, &mut streamed).unwrap();
+            assert_eq!(streamed, format.serialize(&root).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_load_data_cell() {
+        let state = load_from_file("tests/data.tvc").unwrap();
+        let data = state.data.unwrap();
+        std::fs::write("tests/data_cell.boc", write_boc(&data).unwrap()).unwrap();
+        std::fs::write("tests/data_cell.b64", format!("{}\n", encode(write_boc(&data).unwrap()))).unwrap();
+        assert_eq!(load_cell_file("tests/data_cell.boc", "data").unwrap(), data);
+        assert_eq!(load_cell_file("tests/data_cell.b64", "data").unwrap(), data);
+        let err = load_cell_file("tests/data_cell.missing", "data").unwrap_err();
+        assert!(matches!(err.downcast_ref::<LinkerError>(), Some(LinkerError::IoError(_))));
+    }
+
+    #[test]
+    fn test_extract_pubkey() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version3.code")], None).unwrap();
+        let prog = Program::new(parser).unwrap();
+        let data = prog.data().unwrap();
+        assert_eq!(extract_pubkey_ex(&data, prog.pubkey_slot(), 64).unwrap(), [0u8; PUBLIC_KEY_LENGTH]);
+
+        let mut dict = HashmapE::with_bit_len(64);
+        let key = SliceData::load_builder(ptr_to_builder(5).unwrap()).unwrap();
+        dict.set(key, &SliceData::new(vec![0xAB; PUBLIC_KEY_LENGTH].into_iter().chain([0x80]).collect())).unwrap();
+        let mut builder = BuilderData::new();
+        builder.append_bit_one().unwrap().checked_append_reference(dict.data().unwrap().clone()).unwrap();
+        let data = builder.into_cell().unwrap();
+        assert_eq!(extract_pubkey_ex(&data, 5, 64).unwrap(), [0xAB; PUBLIC_KEY_LENGTH]);
+        let err = extract_pubkey(&data).unwrap_err();
+        assert!(matches!(err.downcast_ref::<LinkerError>(), Some(LinkerError::InvalidBoc(_))));
+    }
+
+    #[test]
+    fn test_keypair_from_keystore() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version3.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        prog.set_keypair_from_keystore("tests/key1").unwrap();
+        let public = hex::decode("20e104cd084c64cacd7f3a448442e6424d398127d4333504f6d9f7ef08f130d9").unwrap();
+        assert_eq!(extract_pubkey(&prog.data().unwrap()).unwrap().to_vec(), public);
+        assert!(prog.set_keypair_from_keystore("tests/missing.keys.json").is_err());
+        let err = prog.set_keypair_from_keystore("tests/key_mismatch").unwrap_err();
+        assert!(err.to_string().contains("doesn't match the secret key"));
+    }
+
+    #[test]
+    fn test_data_overflow() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version3.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let table = BuilderData::with_raw(vec![0xEE; 127], 127 * 8).unwrap().into_cell().unwrap();
+        prog.set_data_overflow(table.clone());
+        let data = prog.data().unwrap();
+        assert_eq!(data.references_count(), 2);
+        assert_eq!(data.reference(1).unwrap(), table);
+        assert_eq!(extract_pubkey(&data).unwrap(), [0u8; PUBLIC_KEY_LENGTH]);
+
+        prog.set_deploy_code(Cell::default());
+        let state = prog.compile_to_state(false).unwrap();
+        let data = state.data.unwrap();
+        assert_eq!(data.references_count(), 3);
+        assert_eq!(data.reference(1).unwrap(), table);
+    }
+
+    #[test]
+    fn test_data_key_bits() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version3.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        prog.set_data_key_bits(256);
+        let data = prog.data().unwrap();
+        let dict = load_data_dict(data.clone(), 256).unwrap();
+        assert_eq!(dict.len().unwrap(), 1);
+        dict.iterate_slices(|key, _| {
+            assert_eq!(key.remaining_bits(), 256);
+            Ok(true)
+        }).unwrap();
+        assert_eq!(extract_pubkey_ex(&data, prog.pubkey_slot(), 256).unwrap(), [0u8; PUBLIC_KEY_LENGTH]);
+        assert!(extract_pubkey(&data).is_err());
+
+        prog.set_data_key_bits(0);
+        assert!(prog.data().is_err());
+    }
+
+    #[test]
+    fn test_merge_data_dicts() {
+        let mut dict = HashmapE::with_bit_len(64);
+        let key = SliceData::load_builder(ptr_to_builder(0x7fffffff).unwrap()).unwrap();
+        dict.set(key, &SliceData::new(vec![0x12, 0x80])).unwrap();
+        let mut builder = BuilderData::new();
+        builder.append_bit_one().unwrap().checked_append_reference(dict.data().unwrap().clone()).unwrap();
+        let other = builder.into_cell().unwrap();
+
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version3.code")], None).unwrap();
+        let prog = Program::new(parser).unwrap();
+        let merged = load_data_dict(merge_data_dicts(prog.data().unwrap(), other.clone(), 64).unwrap(), 64).unwrap();
+        assert_eq!(merged.len().unwrap(), 2);
+        let pubkey_key = SliceData::load_builder(ptr_to_builder(prog.engine.persistent_data().0).unwrap()).unwrap();
+        assert!(merged.get(pubkey_key).unwrap().is_some());
+
+        assert!(merge_data_dicts(other.clone(), other, 64).is_err());
+    }
+
+    #[test]
+    fn test_friendly_addresses() {
+        let addr = hex::decode("fcb91a3a3816d0f7b8c2c76108b8a9bc5a6b7a55bd79f8ab101c52db29232260").unwrap();
+        let addresses = friendly_addresses(&addr, &[-1, 0]);
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(addresses[&-1].testnet_bounceable, "kf/8uRo6OBbQ97jCx2EIuKm8Wmt6Vb15+KsQHFLbKSMiYIny");
+        assert_eq!(addresses[&0], FriendlyAddresses::new(0, &addr));
+    }
+
+    #[test]
+    fn test_ton_transfer_uri() {
+        let addr = hex::decode("fcb91a3a3816d0f7b8c2c76108b8a9bc5a6b7a55bd79f8ab101c52db29232260").unwrap();
+        let friendly = FriendlyAddresses::new(-1, &addr);
+        assert_eq!(friendly.mainnet_non_bounceable, "Uf/8uRo6OBbQ97jCx2EIuKm8Wmt6Vb15+KsQHFLbKSMiYG+9");
+        assert_eq!(ton_transfer_uri(&friendly, None), "ton://transfer/Uf_8uRo6OBbQ97jCx2EIuKm8Wmt6Vb15-KsQHFLbKSMiYG-9");
+        assert_eq!(ton_transfer_uri(&friendly, Some(1_500_000_000)),
+            "ton://transfer/Uf_8uRo6OBbQ97jCx2EIuKm8Wmt6Vb15-KsQHFLbKSMiYG-9?amount=1500000000");
+    }
+
     #[test]
     fn test_ticktock() {
         let sources = vec![Path::new("./tests/test_stdlib_sol.tvm"),
@@ -592,9 +1912,16 @@ mod tests {
     }
 
     fn get_version(filename: &str) -> Result<String> {
+        get_version_ex(filename, None)
+    }
+
+    fn get_version_ex(filename: &str, version: Option<&str>) -> Result<String> {
         let parser = ParseEngine::new(vec![Path::new(filename)], None);
         assert_eq!(parser.is_ok(), true);
         let mut prog = Program::new(parser.unwrap()).unwrap();
+        if let Some(version) = version {
+            prog.set_version(version);
+        }
         let file_name = compile_to_file(&mut prog, -1).unwrap();
         let (mut root_slice, _) = load_stateinit(file_name.as_str())?;
         let state = StateInit::construct_from(&mut root_slice)?;
@@ -614,6 +1941,630 @@ mod tests {
             get_version("tests/get-version3.code").unwrap_err().to_string());
     }
 
+    #[test]
+    fn test_try_get_version() {
+        let try_version = |filename: &str| {
+            let parser = ParseEngine::new(vec![Path::new(filename)], None).unwrap();
+            let mut prog = Program::new(parser).unwrap();
+            let file_name = compile_to_file(&mut prog, -1).unwrap();
+            let (mut root_slice, _) = load_stateinit(file_name.as_str()).unwrap();
+            let state = StateInit::construct_from(&mut root_slice).unwrap();
+            try_get_version(state.code.as_ref()).unwrap()
+        };
+        assert_eq!(
+            Some("0.43.0+commit.e8c3d877.mod.Linux.g++".to_string()),
+            try_version("tests/get-version1.code"));
+        assert_eq!(
+            Some("0.43.0+commit.e8c3d877.mod.Linux.g++".to_string()),
+            try_version("tests/get-version2.code"));
+        assert_eq!(None, try_version("tests/get-version3.code"));
+        assert_eq!(None, try_get_version(None).unwrap());
+    }
+
+    #[test]
+    fn test_split_boc() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        prog.set_split_boc(true);
+        let file_name = prog.compile_to_file_ex(-1, Some("tests/split_boc.tvc"), None).unwrap();
+        assert_eq!(file_name, "tests/split_boc.tvc");
+        let state = load_from_file(&file_name).unwrap();
+        let code = read_boc(std::fs::read("tests/split_boc.code.boc").unwrap()).unwrap().withdraw_single_root().unwrap();
+        let data = read_boc(std::fs::read("tests/split_boc.data.boc").unwrap()).unwrap().withdraw_single_root().unwrap();
+        assert_eq!(state.code, Some(code));
+        assert_eq!(state.data, Some(data));
+    }
+
+    #[test]
+    fn test_custom_entry_selector() {
+        let compile = |entry_selector: Option<Lines>| {
+            let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+            let mut prog = Program::new(parser).unwrap();
+            if let Some(lines) = entry_selector {
+                prog.set_entry_selector(lines);
+            }
+            prog.compile_asm(false).unwrap()
+        };
+        let lines = ["PUSHREFCONT", "POPCTR c3", "DUP", "IFNOTJMPREF", "DUP", "EQINT -1", "IFJMPREF", "DUP", "EQINT -2", "IFJMPREF", "THROW 12"]
+            .iter().enumerate()
+            .map(|(i, text)| Line::new(&format!("{}\n", text), "custom.code", i + 1))
+            .collect();
+        let default = compile(None);
+        let custom = compile(Some(lines));
+        assert_ne!(default.repr_hash(), custom.repr_hash());
+        assert_eq!(custom.references_count(), default.references_count());
+        for i in 0..default.references_count() {
+            assert_eq!(custom.reference(i).unwrap(), default.reference(i).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_fixed_code_cache() {
+        let compile = || {
+            let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+            let mut prog = Program::new(parser).unwrap();
+            let code = prog.compile_asm(false).unwrap();
+            (code, prog.dbgmap)
+        };
+        let (code1, dbgmap1) = compile();
+        assert!(FIXED_CODE.lock().unwrap().contains_key("entry-selector"));
+        let (code2, dbgmap2) = compile();
+        assert_eq!(code1, code2);
+        assert_eq!(dbgmap1.len(), dbgmap2.len());
+    }
+
+    #[test]
+    fn test_size_report() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let report = prog.size_report().unwrap();
+        let state = prog.compile_to_state(false).unwrap();
+        assert_eq!(report.boc_bytes, write_boc(&state.serialize().unwrap()).unwrap().len());
+        assert_eq!(report, state_size(&state, SerializationFormat::Default).unwrap());
+        let crc = state_size(&state, SerializationFormat::Crc).unwrap();
+        assert_eq!(crc.boc_bytes, report.boc_bytes + 4);
+        assert_eq!(crc.cells, report.cells);
+
+        let leaf = BuilderData::with_raw(vec![0xAA], 8).unwrap().into_cell().unwrap();
+        let mut code = BuilderData::new();
+        code.checked_append_reference(leaf.clone()).unwrap();
+        code.checked_append_reference(leaf.clone()).unwrap();
+        let mut state = StateInit::default();
+        state.set_code(code.into_cell().unwrap());
+        state.set_data(leaf);
+        let report = state_size(&state, SerializationFormat::Default).unwrap();
+        assert_eq!(report.cells, 3);
+        assert_eq!(report.bits, state.serialize().unwrap().bit_length() + 8);
+    }
+
+    #[test]
+    fn test_debug_map_is_stable() {
+        let compile = || {
+            let sources = vec![Path::new("tests/test_stdlib_sol.tvm"), Path::new("tests/Wallet.code")];
+            let abi = abi::load_abi_json_string("tests/Wallet.abi.json").unwrap();
+            let parser = ParseEngine::new(sources, Some(abi)).unwrap();
+            let mut prog = Program::new(parser).unwrap();
+            prog.compile_asm(false).unwrap();
+            serde_json::to_string_pretty(&prog.dbgmap).unwrap()
+        };
+        assert_eq!(compile(), compile());
+    }
+
+    #[test]
+    fn test_selector_kind() {
+        let compile = |sources: Vec<&Path>, kind| {
+            let parser = ParseEngine::new(sources, None).unwrap();
+            let mut prog = Program::new(parser).unwrap();
+            prog.set_selector_kind(kind);
+            prog.compile_asm(false)
+        };
+        let sources = || vec![Path::new("tests/test_stdlib.tvm"), Path::new("tests/test_macros.code")];
+        let auto = compile(sources(), SelectorKind::Auto).unwrap();
+        assert_eq!(auto, compile(sources(), SelectorKind::Legacy).unwrap());
+        assert_ne!(auto, compile(sources(), SelectorKind::Modern).unwrap());
+
+        let sources = || vec![Path::new("tests/get-version1.code")];
+        assert_eq!(compile(sources(), SelectorKind::Auto).unwrap(), compile(sources(), SelectorKind::Modern).unwrap());
+        let err = compile(sources(), SelectorKind::Legacy).unwrap_err();
+        assert!(matches!(err.downcast_ref::<LinkerError>(), Some(LinkerError::CompileError(_))));
+        assert_eq!(SelectorKind::from_str("modern").unwrap(), SelectorKind::Modern);
+        assert!(SelectorKind::from_str("new").is_err());
+    }
+
+    #[test]
+    fn test_code_salt() {
+        let compile = |source: &str, salt: Option<Cell>| {
+            let parser = ParseEngine::new(vec![Path::new(source)], None).unwrap();
+            let mut prog = Program::new(parser).unwrap();
+            if let Some(salt) = salt {
+                prog.set_code_salt(salt);
+            }
+            prog.compile_asm(false)
+        };
+        let salt = BuilderData::with_raw(vec![0x5A; 4], 32).unwrap().into_cell().unwrap();
+        let plain = compile("tests/get-version1.code", None).unwrap();
+        let salted = compile("tests/get-version1.code", Some(salt.clone())).unwrap();
+        assert_ne!(plain.repr_hash(), salted.repr_hash());
+        assert_eq!(get_code_salt(&salted), Some(salt.clone()));
+        assert_eq!(get_code_salt(&plain), None);
+        assert_eq!(try_get_version(Some(&salted)).unwrap(), try_get_version(Some(&plain)).unwrap());
+
+        let err = compile("tests/get-version3.code", Some(salt)).unwrap_err();
+        assert!(matches!(err.downcast_ref::<LinkerError>(), Some(LinkerError::CompileError(_))));
+    }
+
+    #[test]
+    fn test_check_cell_depth() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let state = prog.compile_to_state(false).unwrap();
+        assert!(check_cell_depth(&state, MAX_CELL_DEPTH).is_ok());
+        let err = check_cell_depth(&state, 1).unwrap_err();
+        assert!(err.to_string().starts_with("code cell depth "));
+        assert!(matches!(err.downcast_ref::<LinkerError>(), Some(LinkerError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_external_method() {
+        let new_prog = || {
+            let sources = vec![Path::new("tests/test_stdlib.tvm"), Path::new("tests/test_macros.code")];
+            Program::new(ParseEngine::new(sources, None).unwrap()).unwrap()
+        };
+        let lib = library_cell(&UInt256::from([0x11; 32])).unwrap();
+        assert_eq!(lib.cell_type(), CellType::LibraryReference);
+
+        let mut prog = new_prog();
+        let code = prog.compile_asm(false).unwrap();
+        let mut prog = new_prog();
+        prog.add_external_method(0x1234, lib.clone());
+        assert_ne!(prog.compile_asm(false).unwrap(), code);
+
+        let mut prog = new_prog();
+        let public = prog.symbols().into_iter().find(|s| s.kind == SymbolKind::Public).unwrap();
+        prog.add_external_method(public.id as u32, lib);
+        assert!(prog.compile_asm(false).is_err());
+    }
+
+    #[test]
+    fn test_virtual_fs() {
+        #[derive(Clone, Default)]
+        struct MemoryFs(std::rc::Rc<std::cell::RefCell<HashMap<String, Vec<u8>>>>);
+        impl Fs for MemoryFs {
+            fn read(&self, path: &str) -> std::io::Result<Vec<u8>> {
+                self.0.borrow().get(path).cloned()
+                    .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+            }
+            fn write(&self, path: &str, data: &[u8]) -> std::io::Result<()> {
+                self.0.borrow_mut().insert(path.to_string(), data.to_vec());
+                Ok(())
+            }
+        }
+
+        let fs = MemoryFs::default();
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        prog.set_fs(Box::new(fs.clone()));
+        prog.set_split_boc(true);
+        let file_name = prog.compile_to_file_ex(-1, Some("virtual/contract.tvc"), None).unwrap();
+        assert!(!Path::new("virtual").exists());
+        let state = load_from_fs(&fs, &file_name).unwrap();
+        assert_eq!(state.code.unwrap().repr_hash(), load_cell_from_fs(&fs, "virtual/contract.code.boc", "code").unwrap().repr_hash());
+        assert!(load_from_fs(&fs, "virtual/missing.tvc").is_err());
+
+        fs.write("virtual/contract.code", b"source").unwrap();
+        let state = load_from_fs(&fs, &file_name).unwrap();
+        let manifest = build_manifest_from_fs(&fs, &[Path::new("virtual/contract.code")], None, Some(&file_name), &state).unwrap();
+        assert_eq!(manifest.sources[0].sha256, sha256_hex(b"source"));
+        assert_eq!(manifest.data_file.unwrap().sha256, sha256_hex(&fs.read(&file_name).unwrap()));
+        assert!(build_manifest_from_fs(&fs, &[Path::new("virtual/missing.code")], None, None, &state).is_err());
+    }
+
+    #[test]
+    fn test_diff_state_init() {
+        let compile = |source: &str, name: &str| {
+            let parser = ParseEngine::new(vec![Path::new(source)], None).unwrap();
+            let mut prog = Program::new(parser).unwrap();
+            prog.compile_to_file_ex(-1, Some(name), None).unwrap()
+        };
+        let old = compile("tests/get-version1.code", "tests/diff_old.tvc");
+        let new = compile("tests/get-version3.code", "tests/diff_new.tvc");
+        assert!(diff_tvc_files(&old, &old).unwrap().is_empty());
+        let diff = diff_tvc_files(&old, &new).unwrap();
+        assert!(diff.code_changed);
+        assert!(!diff.data_changed);
+        assert!(!diff.split_depth_changed && !diff.special_changed && !diff.library_changed);
+        assert!(diff.code_difference.unwrap().starts_with("root"));
+    }
+
+    #[test]
+    fn test_build_state_init() {
+        let compiled = load_from_file("tests/data.tvc").unwrap();
+        let (state, addresses) = build_state_init(compiled.code.clone().unwrap(), compiled.data.clone(), &[0]).unwrap();
+        assert_eq!(state.hash().unwrap(), compiled.hash().unwrap());
+        assert_eq!(addresses[&0], FriendlyAddresses::new(0, compiled.hash().unwrap().as_slice()));
+    }
+
+    #[test]
+    fn test_deploy_code() {
+        let new_prog = || {
+            let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+            Program::new(parser).unwrap()
+        };
+        let compiled = new_prog().compile_to_state(false).unwrap();
+        let loader = BuilderData::with_raw(vec![0xAA], 8).unwrap().into_cell().unwrap();
+        let mut prog = new_prog();
+        prog.set_deploy_code(loader.clone());
+        let state = prog.compile_to_state(false).unwrap();
+        assert_eq!(state.code, Some(loader));
+        let data = state.data.unwrap();
+        assert_eq!(data.reference(1).unwrap(), compiled.code.unwrap());
+        assert_eq!(data.reference(0).unwrap(), compiled.data.unwrap().reference(0).unwrap());
+    }
+
+    #[test]
+    fn test_recompute_address() {
+        let mut state = load_from_file("tests/data.tvc").unwrap();
+        let (address, friendly) = recompute_address(&state, 0).unwrap();
+        assert_eq!(address, state.hash().unwrap());
+        assert_eq!(friendly.mainnet_bounceable, calc_userfriendly_address(0, address.as_slice(), true, false));
+        state.set_data(Cell::default());
+        let (new_address, _) = recompute_address(&state, 0).unwrap();
+        assert_ne!(new_address, address);
+    }
+
+    #[test]
+    fn test_counterfactual_address() {
+        let compiled = load_from_file("tests/data.tvc").unwrap();
+        let code = compiled.code.clone().unwrap();
+        let (address, friendly) = counterfactual_address(&code, compiled.data.as_ref().unwrap(), 0).unwrap();
+        assert_eq!(address, compiled.hash().unwrap());
+        assert_eq!(friendly, FriendlyAddresses::new(0, address.as_slice()));
+        let salted = BuilderData::with_raw(vec![0x01], 8).unwrap().into_cell().unwrap();
+        let (salted_address, _) = counterfactual_address(&code, &salted, 0).unwrap();
+        assert_ne!(salted_address, address);
+    }
+
+    #[test]
+    fn test_friendly_addresses_of_file() {
+        let address = state_address(&load_from_file("tests/data.tvc").unwrap()).unwrap();
+        let friendly = friendly_addresses_of_file("tests/data.tvc", -1).unwrap();
+        assert_eq!(friendly, FriendlyAddresses::new(-1, address.as_slice()));
+        assert!(friendly_addresses_of_file("tests/missing.tvc", -1).is_err());
+    }
+
+    #[test]
+    fn test_check_abi() {
+        let sources = vec![Path::new("./tests/test_stdlib_sol.tvm"), Path::new("./tests/Wallet.code")];
+        let abi = abi::load_abi_json_string("./tests/Wallet.abi.json").unwrap();
+        let mut prog = Program::new(ParseEngine::new(sources.clone(), Some(abi)).unwrap()).unwrap();
+        prog.set_check_abi(true);
+        assert!(prog.compile_to_state(false).is_ok());
+
+        let abi = abi::load_abi_json_string("./tests/test_complex.abi.json").unwrap();
+        let mut prog = Program::new(ParseEngine::new(sources, Some(abi)).unwrap()).unwrap();
+        assert!(prog.compile_to_state(false).is_ok());
+        prog.set_check_abi(true);
+        let err = prog.compile_to_state(false).unwrap_err();
+        assert_eq!(err.to_string(), "ABI functions are not implemented in the sources: setValues");
+    }
+
+    #[test]
+    fn test_warnings() {
+        let sources = vec![Path::new("./tests/test_stdlib_sol.tvm"), Path::new("./tests/Wallet.code")];
+        let abi = abi::load_abi_json_string("./tests/Wallet.abi.json").unwrap();
+        let mut prog = Program::new(ParseEngine::new(sources, Some(abi)).unwrap()).unwrap();
+        prog.set_check_abi(true);
+        prog.compile_to_state(false).unwrap();
+        prog.compile_to_state(false).unwrap();
+        let fallback = "public function fallback is missing in the ABI".to_string();
+        assert_eq!(prog.warnings().iter().filter(|warning| **warning == fallback).count(), 1);
+
+        let parser = ParseEngine::new(vec![Path::new("./tests/test_unused_private.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        prog.set_verbosity(Verbosity::Quiet);
+        prog.compile_asm(false).unwrap();
+        assert_eq!(prog.warnings(), ["private function unused is not reachable from any public or internal function".to_string()]);
+    }
+
+    #[test]
+    fn test_state_address_pruned() {
+        let state = load_from_file("tests/data.tvc").unwrap();
+        let root = state.serialize().unwrap();
+        let root_hash = root.repr_hash();
+        let proof = MerkleProof::create(&root, |hash| hash == &root_hash).unwrap();
+        let pruned = StateInit::construct_from_cell(proof.proof).unwrap();
+        assert_eq!(pruned.code.as_ref().unwrap().cell_type(), CellType::PrunedBranch);
+        assert_ne!(pruned.hash().unwrap(), state.hash().unwrap());
+        assert_eq!(state_address(&pruned).unwrap(), state.hash().unwrap());
+        assert_eq!(state_address(&state).unwrap(), state.hash().unwrap());
+    }
+
+    #[test]
+    fn test_verify_against() {
+        let source = Path::new("tests/get-version1.code");
+        let parser = ParseEngine::new(vec![source], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let file_name = prog.compile_to_file_ex(-1, Some("tests/verify_against.tvc"), None).unwrap();
+        assert_eq!(verify_against(vec![source], None, &file_name).unwrap(), None);
+        assert!(verify_against(vec![Path::new("tests/get-version3.code")], None, &file_name).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_compile_to_file_json() {
+        let sources = vec![Path::new("tests/test_stdlib.tvm"), Path::new("tests/test_macros.code")];
+        let parser = ParseEngine::new(sources, None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let summary = prog.compile_to_file_json(&[-1, 0], Some("tests/compile_summary.tvc"), None).unwrap();
+        assert_eq!(summary["output_path"], "tests/compile_summary.tvc");
+        assert_eq!(summary["methods"]["main"], 0x0D6E4079);
+        let state = load_from_file("tests/compile_summary.tvc").unwrap();
+        let address = state.hash().unwrap();
+        assert_eq!(summary["address_hash"], format!("{:x}", address));
+        assert_eq!(summary["addresses"]["0"]["mainnet_bounceable"], calc_userfriendly_address(0, address.as_slice(), true, false));
+        assert_eq!(summary["addresses"]["-1"]["testnet_non_bounceable"], calc_userfriendly_address(-1, address.as_slice(), false, true));
+        assert_eq!(summary["size"]["boc_bytes"], std::fs::read("tests/compile_summary.tvc").unwrap().len());
+        assert_eq!(summary["code_hash"], state.code.unwrap().repr_hash().to_hex_string());
+    }
+
+    #[test]
+    fn test_set_version() {
+        assert_eq!(
+            "custom-build".to_string(),
+            get_version_ex("tests/get-version1.code", Some("custom-build")).unwrap());
+        assert_eq!(
+            "custom-build".to_string(),
+            get_version_ex("tests/get-version3.code", Some("custom-build")).unwrap());
+    }
+
+    #[test]
+    fn test_default_tvc_extension() {
+        let mut state = StateInit::default();
+        state.set_code(Cell::default());
+        let name = save_to_file(state.clone(), Some("tests/default_ext"), 0, true).unwrap();
+        assert_eq!(name, "tests/default_ext.tvc");
+        assert_eq!(load_from_file(&name).unwrap(), state);
+        let name = save_to_file(state, Some("tests/default_ext.boc"), 0, true).unwrap();
+        assert_eq!(name, "tests/default_ext.boc");
+    }
+
+    #[test]
+    fn test_compile_with_manifest() {
+        let job = CompileJob {
+            sources: vec!["tests/get-version1.code".into()],
+            out_file: Some("tests/manifest.tvc".to_string()),
+            ..Default::default()
+        };
+        let (summary, manifest) = compile_with_manifest(job.clone()).unwrap();
+        let state = load_from_file(&summary.output_path).unwrap();
+        assert_eq!(manifest.sources.len(), 1);
+        assert_eq!(manifest.sources[0].path, "tests/get-version1.code");
+        assert_eq!(manifest.sources[0].sha256, sha256_hex(&std::fs::read("tests/get-version1.code").unwrap()));
+        assert_eq!(manifest.abi_sha256, None);
+        assert_eq!(manifest.code_hash, Some(state.code.unwrap().repr_hash().to_hex_string()));
+        let (_, again) = compile_with_manifest(job).unwrap();
+        assert_eq!(again.to_json().unwrap(), manifest.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_code_postprocessor() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let code = prog.compile_asm(false).unwrap();
+        prog.set_code_postprocessor(Box::new(|code| {
+            let mut builder = BuilderData::new();
+            builder.checked_append_reference(code)?;
+            builder.into_cell()
+        }));
+        let state = prog.compile_to_state(false).unwrap();
+        assert_eq!(state.code.unwrap().reference(0).unwrap(), code);
+
+        prog.set_code_postprocessor(Box::new(|_| Err(format_err!("unsupported"))));
+        let err = prog.compile_to_state(false).unwrap_err();
+        assert!(matches!(err.downcast_ref::<LinkerError>(), Some(LinkerError::CompileError(_))));
+    }
+
+    #[test]
+    fn test_write_boc() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let mut buffer = Vec::new();
+        prog.write_boc(&mut buffer, None).unwrap();
+        let state = StateInit::construct_from_bytes(&buffer).unwrap();
+        let file_name = prog.compile_to_file_ex(0, Some("tests/write_boc.tvc"), None).unwrap();
+        let saved = load_from_file(&file_name).unwrap();
+        assert_eq!(state.code, saved.code);
+        assert_eq!(state.data, saved.data);
+    }
+
+    #[test]
+    fn test_boc_hex() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        prog.set_boc_format(SerializationFormat::Crc);
+        let boc_hex = prog.boc_hex(None).unwrap();
+        let file_name = prog.compile_to_file_ex(0, Some("tests/boc_hex.tvc"), None).unwrap();
+        assert_eq!(boc_hex, hex::encode(std::fs::read(file_name).unwrap()));
+    }
+
+    #[test]
+    fn test_load_message() {
+        let mut msg = Message::with_ext_in_header(ExternalInboundMessageHeader {
+            src: MsgAddressExt::default(),
+            dst: MsgAddressInt::with_standart(None, 0, [0x11; 32].into()).unwrap(),
+            import_fee: 0x1234u64.into(),
+        });
+        let mut state = StateInit::default();
+        state.set_code(Cell::default());
+        msg.set_state_init(state);
+        msg.set_body(SliceData::new(vec![0x55, 0x80]));
+        std::fs::write("tests/load_message.boc", write_boc(&msg.serialize().unwrap()).unwrap()).unwrap();
+        assert_eq!(load_message("tests/load_message.boc").unwrap(), msg);
+
+        let err = load_message("tests/missing.boc").unwrap_err();
+        assert!(matches!(err.downcast_ref::<LinkerError>(), Some(LinkerError::IoError(_))));
+    }
+
+    #[test]
+    fn test_debug_guards() {
+        let compile = |debug_guards: bool, delta: Option<i32>| {
+            let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+            let mut prog = Program::new(parser).unwrap();
+            prog.set_debug_guards(debug_guards);
+            if let Some(delta) = delta {
+                prog.set_stack_delta(-1i32 as u32, delta); // main_internal
+            }
+            prog.public_method_dict(false).unwrap()
+        };
+        let release = compile(false, Some(0));
+        assert_eq!(release, compile(true, None));
+        assert_ne!(release, compile(true, Some(0)));
+
+        let run = |body: &str, delta: i32| {
+            let body: Lines = body.lines().enumerate().map(|(i, text)| Line::new(&format!("{}\n", text), "test", i + 1)).collect();
+            let code = crate::methdict::guard_method(&body, delta).iter().map(|line| line.text.clone()).collect::<String>();
+            let mut state = StateInit::default();
+            state.set_code(ton_labs_assembler::compile_code_to_cell(&format!("ACCEPT\n{}", code)).unwrap());
+            state.set_data(Cell::default());
+            let addr = MsgAddressInt::with_standart(None, 0, [0; 32].into()).unwrap();
+            let (exit_code, _, is_success) = call_contract(addr, state, TestCallParams {
+                balance: None,
+                msg_info: MsgInfo { balance: None, src: None, now: 1, bounced: false, body: None },
+                config: None,
+                key_file: None,
+                ticktock: None,
+                gas_limit: Some(GasLimit::Credit(10000)),
+                action_decoder: None::<fn(SliceData, bool)>,
+                trace_level: TraceLevel::None,
+                debug_info: None,
+                trace_callback: None,
+                capabilities: 0x42E,
+                vm_version: None,
+                block_time_override: None,
+                trace_filter: None,
+                global_id: None,
+            }).unwrap();
+            (exit_code, is_success)
+        };
+        assert!(run("PUSHINT 1\nDROP", 0).1);
+        assert!(run("PUSHINT 1", 1).1);
+        assert!(run("DROP", -1).1);
+        // takes two arguments and returns one or two results
+        assert!(run("DROP\nDROP\nPUSHINT 1", -1).1);
+        assert!(run("DROP\nDROP\nPUSHINT 1\nPUSHINT 2", 0).1);
+        assert_eq!(run("PUSHINT 1", 0), (crate::methdict::STACK_GUARD_EXCEPTION as i32, false));
+        assert_eq!(run("DROP", 0), (crate::methdict::STACK_GUARD_EXCEPTION as i32, false));
+    }
+
+    #[test]
+    fn test_dump_annotated_asm() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let text = prog.dump_annotated_asm().unwrap();
+        assert!(text.contains(" ;; tests/get-version1.code:5\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_set_method_id_scheme() {
+        let asm = "\t.globl  seqno\n\t.public seqno\n\t.type   seqno, @function\nPUSHINT 1\n";
+        let mut prog = Program::from_str(asm, None).unwrap();
+        let name_hash = crate::abi::gen_method_id(None, "seqno", MethodIdScheme::NameHash);
+        assert!(prog.engine.publics().contains_key(&name_hash));
+        prog.set_method_id_scheme(MethodIdScheme::Crc16).unwrap();
+        assert_eq!(prog.engine.publics().keys().collect::<Vec<_>>(), vec![&85143]);
+        assert_eq!(prog.engine.global_name(85143), Some("seqno".to_string()));
+    }
+
+    #[test]
+    fn test_from_str() {
+        let asm = std::fs::read_to_string("tests/get-version1.code").unwrap();
+        let mut prog = Program::from_str(&asm, None).unwrap();
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        assert_eq!(prog.compile_asm(false).unwrap(), Program::new(parser).unwrap().compile_asm(false).unwrap());
+        assert!(Program::from_str(".selector\n.pragma unknown-pragma\n", None).is_err());
+    }
+
+    #[test]
+    fn test_compile_batch() {
+        let job = |source: &str, out_file: &str| CompileJob {
+            sources: vec![source.into()],
+            out_file: Some(out_file.to_string()),
+            ..Default::default()
+        };
+        let results = compile_batch(vec![
+            job("tests/get-version1.code", "tests/batch1.tvc"),
+            job("tests/missing.code", "tests/batch2.tvc"),
+            job("tests/get-version3.code", "tests/batch3.tvc"),
+        ]);
+        assert_eq!(results.len(), 3);
+        let summary = results[0].as_ref().unwrap();
+        let state = load_from_file("tests/batch1.tvc").unwrap();
+        assert_eq!(summary.output_path, "tests/batch1.tvc");
+        assert_eq!(summary.address, state.hash().unwrap());
+        assert_eq!(summary.code_hash, state.code.unwrap().repr_hash());
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().output_path, "tests/batch3.tvc");
+        assert_eq!(CompileJob::default().wc, -1);
+
+        let wallet = |ctor_params: Option<&str>| CompileJob {
+            sources: vec!["tests/test_stdlib_sol.tvm".into(), "tests/Wallet.code".into()],
+            abi_json: Some(abi::load_abi_json_string("tests/Wallet.abi.json").unwrap()),
+            ctor_params: ctor_params.map(str::to_string),
+            out_file: Some("tests/batch_wallet.tvc".to_string()),
+            ..Default::default()
+        };
+        let params = format!(r#"{{"subscription": "0:{}"}}"#, "1".repeat(64));
+        let results = compile_batch(vec![wallet(None), wallet(Some(&params)), wallet(Some(&params))]);
+        let plain = results[0].as_ref().unwrap();
+        let with_params = results[1].as_ref().unwrap();
+        assert_ne!(plain.address, with_params.address);
+        assert_eq!(with_params.address, results[2].as_ref().unwrap().address);
+        let mut job = wallet(Some(&params));
+        job.abi_json = None;
+        assert!(compile_batch(vec![job])[0].is_err());
+    }
+
+    #[test]
+    fn test_code_method_ids() {
+        let parser = ParseEngine::new(vec![Path::new("tests/test_unused_private.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        prog.set_verbosity(Verbosity::Quiet);
+        let file_name = prog.compile_to_file_ex(-1, Some("tests/method_ids.tvc"), None).unwrap();
+        // the pragma keeps the unused private function in the dictionary too
+        let mut expected: Vec<i32> = prog.symbols().iter().map(|symbol| symbol.id as i32).collect();
+        expected.sort_unstable();
+        assert_eq!(load_method_ids(&file_name).unwrap(), expected);
+        assert!(code_method_ids(&Cell::default()).is_err());
+        let compile = |kind| {
+            let sources = vec![Path::new("tests/test_stdlib.tvm"), Path::new("tests/test_macros.code")];
+            let mut prog = Program::new(ParseEngine::new(sources, None).unwrap()).unwrap();
+            prog.set_selector_kind(kind);
+            prog.compile_asm(false).unwrap()
+        };
+        let legacy = code_method_ids(&compile(SelectorKind::Legacy)).unwrap();
+        assert!(!legacy.is_empty());
+        assert_eq!(legacy, code_method_ids(&compile(SelectorKind::Modern)).unwrap());
+
+        let asm = std::fs::read_to_string("tests/get-version1.code").unwrap();
+        let plain = Program::from_str(&asm, None).unwrap().compile_asm(false).unwrap();
+        for pragma in ["selector-func-solidity", "selector-old-sol"] {
+            let upgrade = Program::from_str(&format!(".pragma {}\n{}", pragma, asm), None).unwrap().compile_asm(false).unwrap();
+            assert_eq!(upgrade.references_count(), 1);
+            assert_eq!(code_method_ids(&upgrade).unwrap(), code_method_ids(&plain).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_emit_version() {
+        let parser = ParseEngine::new(vec![Path::new("tests/get-version1.code")], None).unwrap();
+        let mut prog = Program::new(parser).unwrap();
+        let with_version = prog.compile_asm(false).unwrap();
+        prog.set_emit_version(false);
+        let without_version = prog.compile_asm(false).unwrap();
+        assert_eq!(with_version.reference(0).unwrap().references_count(), 2);
+        assert_eq!(without_version.reference(0).unwrap().references_count(), 1);
+        assert_eq!(with_version.reference(0).unwrap().reference(0).unwrap(), without_version.reference(0).unwrap().reference(0).unwrap());
+    }
+
     #[test]
     fn test_mycode() {
         let sources = vec![Path::new("tests/test_stdlib_sol.tvm"), Path::new("tests/mycode.code")];
@@ -11,30 +11,38 @@
  * limitations under the License.
  */
 
+use abi_json::ParamType;
 use ed25519_dalek::Signer;
-use failure::format_err;
+use failure::{bail, format_err};
+use crate::abi::{load_abi_contract, load_abi_json_string};
 use crate::keyman::KeypairManager;
+use crate::parser::Symbols;
 use log::Level::Error;
-use crate::printer::msg_printer;
+use crate::printer::{msg_printer, print_action};
 use crate::program::{load_from_file, get_now};
 use simplelog::{SimpleLogger, Config, LevelFilter};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use ton_vm::executor::{Engine, EngineTraceInfo, EngineTraceInfoType, gas::gas_state::Gas};
 use ton_vm::error::tvm_exception;
 use ton_vm::stack::{StackItem, Stack, savelist::SaveList, integer::IntegerData};
 use ton_vm::SmartContractInfo;
-use ton_types::{AccountId, BuilderData, Cell, SliceData, Result, Status};
+use ton_types::{
+    AccountId, BuilderData, Cell, IBitstring, SliceData, Result, Status, UInt256, read_single_root_boc, write_boc,
+    dictionary::{HashmapE, HashmapType},
+};
 use ton_block::{
-    CurrencyCollection, Deserializable, ExternalInboundMessageHeader, Grams,
-    InternalMessageHeader, Message, MsgAddressExt, MsgAddressInt, OutAction,
-    OutActions, Serializable, StateInit,
+    Account, ConfigParams, CurrencyCollection, Deserializable, ExternalInboundMessageHeader, GlobalCapabilities,
+    Grams, InternalMessageHeader, Message, MsgAddressExt, MsgAddressInt, OutAction,
+    MsgAddressIntOrNone, OutActions, Serializable, StateInit,
 };
-use ton_labs_assembler::DbgInfo;
+use ton_labs_assembler::{DbgInfo, DbgPos};
 
 const DEFAULT_ACCOUNT_BALANCE: &str = "100000000000";
+pub const DEFAULT_CAPABILITIES: u64 = 0x42E | 0x800000;   // STCONT turned on
 
 fn create_external_inbound_msg(src: MsgAddressExt, dst: MsgAddressInt, body: Option<SliceData>) -> Message {
     let hdr = ExternalInboundMessageHeader {
@@ -99,8 +107,13 @@ fn initialize_registers(
     balance: CurrencyCollection,
     config_params: Option<Cell>,
     capabilities: u64,
+    global_id: Option<i32>,
 ) -> Result<SaveList> {
     let mut ctrls = SaveList::new();
+    let config_params = match global_id {
+        Some(global_id) => set_global_id(config_params, global_id)?,
+        None => config_params,
+    };
     let info = SmartContractInfo {
         capabilities,
         balance,
@@ -116,6 +129,17 @@ fn initialize_registers(
     Ok(ctrls)
 }
 
+/// Puts the global id of the network into the config param 19 of the config dictionary,
+/// where the contracts read it from.
+fn set_global_id(config_params: Option<Cell>, global_id: i32) -> Result<Option<Cell>> {
+    let mut config = HashmapE::with_hashmap(32, config_params);
+    let key = SliceData::load_cell(19u32.serialize()?)?;
+    let mut value = BuilderData::new();
+    value.append_i32(global_id)?;
+    config.setref(key, &value.into_cell()?)?;
+    Ok(config.data().cloned())
+}
+
 fn init_logger(debug: bool) -> Status {
     SimpleLogger::init(
         if debug {LevelFilter::Trace } else { LevelFilter::Info },
@@ -174,24 +198,17 @@ fn decode_actions<F>(actions: StackItem, state: &mut StateInit, action_decoder:
         let actions: OutActions = OutActions::construct_from(&mut SliceData::load_cell(cell)?)?;
         println!("Output actions:\n----------------");
         for act in actions {
+            println!("{}", print_action(&act)?);
             match act {
                 OutAction::SendMsg{mode: _, out_msg } => {
-                    println!("Action(SendMsg):\n{}", msg_printer(&out_msg)?);
                     if let Some(b) = out_msg.body() {
                         action_decoder(b, out_msg.is_internal());
                     }
                 },
                 OutAction::SetCode{ new_code: code } => {
-                    println!("Action(SetCode)");
                     state.code = Some(code);
                 },
-                OutAction::ReserveCurrency { .. } => {
-                    println!("Action(ReserveCurrency)");
-                },
-                OutAction::ChangeLibrary { .. } => {
-                    println!("Action(ChangeLibrary)");
-                },
-                _ => println!("Action(Unknown)"),
+                _ => (),
             };
         }
     }
@@ -240,6 +257,42 @@ pub struct MsgInfo<'a> {
     pub body: Option<SliceData>,
 }
 
+impl<'a> MsgInfo<'a> {
+    /// Creates inbound message info emulating the bounce of `original` outbound internal message.
+    /// The body is the 0xffffffff prefix followed by the first 256 bits of the original body.
+    pub fn bounced_from(
+        original: &Message,
+        balance: Option<&'a str>,
+        src: Option<&'a str>,
+        now: u32,
+    ) -> Result<Self> {
+        if !original.is_internal() {
+            bail!("only internal messages can be bounced");
+        }
+        Ok(MsgInfo {
+            balance,
+            src,
+            now,
+            bounced: true,
+            body: Some(build_bounced_body(original.body())?),
+        })
+    }
+}
+
+const BOUNCED_BODY_PREFIX: u32 = 0xffffffff;
+const BOUNCED_BODY_BITS: usize = 256;
+
+pub fn build_bounced_body(original_body: Option<SliceData>) -> Result<SliceData> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(BOUNCED_BODY_PREFIX)?;
+    if let Some(mut body) = original_body {
+        let bits = std::cmp::min(body.remaining_bits(), BOUNCED_BODY_BITS);
+        let data = body.get_next_bits(bits)?;
+        builder.append_raw(&data, bits)?;
+    }
+    SliceData::load_builder(builder)
+}
+
 pub fn load_debug_info(filename: &str) -> Option<DbgInfo> {
     File::open(filename)
         .ok()
@@ -254,13 +307,213 @@ pub fn load_config(filename: &str) -> Option<Cell> {
     data.into_cell().reference(0).ok()
 }
 
+pub fn config_capabilities(config: Option<&Cell>) -> u64 {
+    match config {
+        Some(config_cell) => {
+            let config_params = ConfigParams::with_address_and_root(
+                UInt256::from_str(&"5".repeat(64)).unwrap(), // -1:5555...
+                config_cell.clone());
+            config_params.capabilities()
+        }
+        None => DEFAULT_CAPABILITIES
+    }
+}
+
 #[derive(PartialEq)]
 pub enum TraceLevel {
     Full,
+    /// Same as `Full`, but only the stack items changed since the previous step are printed.
+    StackDiff,
     Minimal,
     None
 }
 
+/// Gas limit for the computing phase, in gas units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GasLimit {
+    /// Absolute limit, the contract can spend up to this amount of gas.
+    Limit(i64),
+    /// Free gas credit given to an external message: the limit is zero until the contract
+    /// executes ACCEPT, then it is raised to the maximum.
+    Credit(i64),
+}
+
+const CAPABILITY_NAMES: &[(&str, u64)] = &[
+    ("CapIhrEnabled",             GlobalCapabilities::CapIhrEnabled as u64),
+    ("CapCreateStatsEnabled",     GlobalCapabilities::CapCreateStatsEnabled as u64),
+    ("CapBounceMsgBody",          GlobalCapabilities::CapBounceMsgBody as u64),
+    ("CapReportVersion",          GlobalCapabilities::CapReportVersion as u64),
+    ("CapSplitMergeTransactions", GlobalCapabilities::CapSplitMergeTransactions as u64),
+    ("CapShortDequeue",           GlobalCapabilities::CapShortDequeue as u64),
+    ("CapMbppEnabled",            GlobalCapabilities::CapMbppEnabled as u64),
+    ("CapFastStorageStat",        GlobalCapabilities::CapFastStorageStat as u64),
+    ("CapInitCodeHash",           GlobalCapabilities::CapInitCodeHash as u64),
+    ("CapOffHypercube",           GlobalCapabilities::CapOffHypercube as u64),
+    ("CapMycode",                 GlobalCapabilities::CapMycode as u64),
+    ("CapSetLibCode",             GlobalCapabilities::CapSetLibCode as u64),
+    ("CapFixTupleIndexBug",       GlobalCapabilities::CapFixTupleIndexBug as u64),
+    ("CapRemp",                   GlobalCapabilities::CapRemp as u64),
+    ("CapDelections",             GlobalCapabilities::CapDelections as u64),
+    ("CapFullBodyInBounced",      GlobalCapabilities::CapFullBodyInBounced as u64),
+    ("CapStorageFeeToTvm",        GlobalCapabilities::CapStorageFeeToTvm as u64),
+    ("CapCopyleft",               GlobalCapabilities::CapCopyleft as u64),
+    ("CapIndexAccounts",          GlobalCapabilities::CapIndexAccounts as u64),
+    ("CapsTvmBugfixes2022",       GlobalCapabilities::CapsTvmBugfixes2022 as u64),
+    ("CapWorkchains",             GlobalCapabilities::CapWorkchains as u64),
+    ("CapStcontNewFormat",        GlobalCapabilities::CapStcontNewFormat as u64),
+    ("CapFastStorageStatBugfix",  GlobalCapabilities::CapFastStorageStatBugfix as u64),
+    ("CapResolveMerkleCell",      GlobalCapabilities::CapResolveMerkleCell as u64),
+    ("CapSignatureWithId",        GlobalCapabilities::CapSignatureWithId as u64),
+];
+
+/// Capabilities changing the behaviour and the gas prices of VM instructions.
+const VM_VERSION_CAPABILITIES: u64 =
+    GlobalCapabilities::CapsTvmBugfixes2022 as u64 | GlobalCapabilities::CapStcontNewFormat as u64;
+
+/// Version of the VM behaviour, including the gas model. Pinning it keeps the gas consumption
+/// of tests stable when the default capabilities change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VmVersion {
+    /// The VM without any of the versioned changes.
+    Initial,
+    /// With the fixes of 2022 (`CapsTvmBugfixes2022`).
+    Bugfixes2022,
+    /// With all the versioned changes known to the linker.
+    Current,
+}
+
+impl VmVersion {
+    pub fn capabilities(&self) -> u64 {
+        match self {
+            VmVersion::Initial => 0,
+            VmVersion::Bugfixes2022 => GlobalCapabilities::CapsTvmBugfixes2022 as u64,
+            VmVersion::Current => VM_VERSION_CAPABILITIES,
+        }
+    }
+
+    /// Replaces the versioned capabilities of the mask with the ones of this version.
+    pub fn apply(&self, capabilities: u64) -> u64 {
+        capabilities & !VM_VERSION_CAPABILITIES | self.capabilities()
+    }
+}
+
+impl FromStr for VmVersion {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "initial" => Ok(VmVersion::Initial),
+            "2022" => Ok(VmVersion::Bugfixes2022),
+            "current" => Ok(VmVersion::Current),
+            _ => Err(format_err!("unknown VM version {}, expected initial, 2022 or current", s)),
+        }
+    }
+}
+
+/// Set of VM capabilities assembled from `GlobalCapabilities` values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_list(caps: &[GlobalCapabilities]) -> Self {
+        caps.iter().fold(Self::new(), |acc, cap| acc.with(*cap))
+    }
+
+    pub fn with(mut self, cap: GlobalCapabilities) -> Self {
+        self.0 |= cap as u64;
+        self
+    }
+
+    pub fn has(&self, cap: GlobalCapabilities) -> bool {
+        let cap = cap as u64;
+        self.0 & cap == cap
+    }
+
+    pub fn mask(&self) -> u64 {
+        self.0
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        CAPABILITY_NAMES.iter()
+            .filter(|(_, value)| self.0 & value == *value)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let known = CAPABILITY_NAMES.iter().fold(0, |acc, (_, value)| acc | value);
+        let mut items = self.names().iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        if self.0 & !known != 0 {
+            items.push(format!("0x{:x}", self.0 & !known));
+        }
+        if items.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", items.join(", "))
+        }
+    }
+}
+
+impl From<u64> for Capabilities {
+    fn from(mask: u64) -> Self {
+        Capabilities(mask)
+    }
+}
+
+impl FromStr for Capabilities {
+    type Err = failure::Error;
+
+    /// Parses a comma-separated list of capability names (e.g. `CapMycode,CapInitCodeHash`).
+    /// Hexadecimal masks prefixed with `0x` are accepted as list items too.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut mask = 0u64;
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            if let Some(hex) = name.strip_prefix("0x") {
+                mask |= u64::from_str_radix(hex, 16)
+                    .map_err(|e| format_err!("invalid capabilities mask {}: {}", name, e))?;
+            } else {
+                let (_, value) = CAPABILITY_NAMES.iter()
+                    .find(|(known, _)| known.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| format_err!("unknown capability: {}", name))?;
+                mask |= value;
+            }
+        }
+        Ok(Capabilities(mask))
+    }
+}
+
+/// Hashes of the cells compiled from the function `name`, i.e. from the lines since its declaration
+/// up to the declaration of the next function in the same file.
+pub fn method_cells(debug_info: &DbgInfo, symbols: &Symbols, name: &str) -> Result<HashSet<UInt256>> {
+    let pos = symbols.iter()
+        .find(|symbol| symbol.name == name)
+        .ok_or_else(|| format_err!("function {} is not found", name))?
+        .pos.clone()
+        .ok_or_else(|| format_err!("function {} has no source position", name))?;
+    let end = symbols.iter()
+        .filter_map(|symbol| symbol.pos.as_ref())
+        .filter(|other| other.filename == pos.filename && other.line > pos.line)
+        .map(|other| other.line)
+        .min()
+        .unwrap_or(usize::MAX);
+    Ok(debug_info.iter()
+        .filter(|(_, offsets)| offsets.values()
+            .any(|other| other.filename == pos.filename && other.line >= pos.line && other.line < end))
+        .map(|(hash, _)| hash.clone())
+        .collect())
+}
+
+/// Returns the source position of the first command of the cell with the given hash.
+pub fn source_for<'a>(debug_info: &'a DbgInfo, hash: &UInt256) -> Option<&'a DbgPos> {
+    debug_info.get(hash)?.values().next()
+}
+
 fn get_position(info: &EngineTraceInfo, debug_info: &Option<DbgInfo>) -> Option<String> {
     if let Some(debug_info) = debug_info {
         let cell_hash = info.cmd_code.cell().repr_hash();
@@ -316,17 +569,91 @@ fn trace_callback(_engine: &Engine, info: &EngineTraceInfo, extended: bool, debu
     println!("----------------------------------------\n");
 }
 
+fn trace_callback_stack_diff(_engine: &Engine, info: &EngineTraceInfo, debug_info: &Option<DbgInfo>, prev_stack: &Mutex<Vec<String>>) {
+    if info.info_type == EngineTraceInfoType::Dump {
+        println!("{}", info.cmd_str);
+        return
+    }
+    println!("{}: {}", info.step, info.cmd_str);
+    println!("{} {}", info.cmd_code.remaining_bits(), info.cmd_code.to_hex_string());
+    println!("\nGas: {} ({})", info.gas_used, info.gas_cmd);
+    if let Some(position) = get_position(info, debug_info) {
+        println!("Position: {}", position);
+    }
+    let stack = info.stack.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+    let mut prev_stack = prev_stack.lock().unwrap();
+    println!("\n--- Stack changes ({} items) -----------", stack.len());
+    print!("{}", stack_diff(&prev_stack, &stack));
+    println!("----------------------------------------\n");
+    *prev_stack = stack;
+}
+
+/// Lists the items popped from the `prev` stack with `-` and the items pushed to it with `+`.
+/// Both stacks are given from the bottom to the top, the common bottom part is omitted.
+fn stack_diff(prev: &[String], cur: &[String]) -> String {
+    let common = prev.iter().zip(cur.iter()).take_while(|(a, b)| a == b).count();
+    let mut out = String::new();
+    for item in prev[common..].iter().rev() {
+        out.push_str(&format!("- {}\n", item));
+    }
+    for item in &cur[common..] {
+        out.push_str(&format!("+ {}\n", item));
+    }
+    out
+}
+
+/// Callback called after each executed VM command. The engine can be used to inspect
+/// control registers, e.g. `engine.ctrl(4)` for the persistent data.
+pub type TraceCallback = Box<dyn Fn(&Engine, &EngineTraceInfo) + Send + Sync>;
+
+/// Trace callback printing control registers c4 (persistent data), c5 (actions) and c7 (context).
+pub fn trace_registers(engine: &Engine, info: &EngineTraceInfo) {
+    if info.info_type == EngineTraceInfoType::Dump {
+        return
+    }
+    println!("--- Control registers ------------------");
+    for index in [4, 5, 7] {
+        match engine.ctrl(index) {
+            Ok(item) => println!("c{}: {}", index, item),
+            Err(_) => println!("c{}: <not set>", index),
+        }
+    }
+    println!("----------------------------------------\n");
+}
+
 pub struct TestCallParams<'a, F: Fn(SliceData, bool)> {
     pub balance: Option<&'a str>,
     pub msg_info: MsgInfo<'a>,
     pub config: Option<Cell>,
     pub key_file: Option<Option<&'a str>>,
     pub ticktock: Option<i8>,
-    pub gas_limit: Option<i64>,
+    pub gas_limit: Option<GasLimit>,
     pub action_decoder: Option<F>,
     pub trace_level: TraceLevel,
     pub debug_info: Option<DbgInfo>,
-    pub capabilities: u64
+    /// Called after each command in addition to the trace of `trace_level`.
+    pub trace_callback: Option<TraceCallback>,
+    pub capabilities: u64,
+    /// If set, the versioned capabilities are taken from this version instead of `capabilities`.
+    pub vm_version: Option<VmVersion>,
+    /// If set, the VM sees this block time in c7 instead of `now` of the message,
+    /// e.g. to test deadlines far in the future.
+    pub block_time_override: Option<u32>,
+    /// If set, the trace of `trace_level` is printed only for the commands of these cells,
+    /// e.g. of one function, see `method_cells`.
+    pub trace_filter: Option<HashSet<UInt256>>,
+    /// If set, the VM sees this global id of the network in the config, e.g. to test
+    /// the contract logic which differs for the mainnet and the testnet.
+    pub global_id: Option<i32>,
+}
+
+/// Gas spent by the computing phase, split by the moment the free gas credit is over.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GasUsage {
+    /// Gas spent within the free gas credit of an external message, i.e. before ACCEPT.
+    pub credit: i64,
+    /// Gas paid from the balance of the contract.
+    pub paid: i64,
 }
 
 pub fn call_contract<F>(
@@ -335,6 +662,106 @@ pub fn call_contract<F>(
     params: TestCallParams<F>,
 ) -> Result<(i32, StateInit, bool)>
     where F: Fn(SliceData, bool)
+{
+    let (exit_code, state_init, is_vm_success, _) = call_contract_ex(addr, state_init, params)?;
+    Ok((exit_code, state_init, is_vm_success))
+}
+
+/// Source of `now` for a sequence of test calls, advances by `delta` seconds after each message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScenarioClock {
+    now: u32,
+    delta: u32,
+}
+
+impl ScenarioClock {
+    pub fn new(start: u32, delta: u32) -> Self {
+        ScenarioClock { now: start, delta }
+    }
+
+    /// Time of the next message.
+    pub fn now(&self) -> u32 {
+        self.now
+    }
+
+    /// Returns the time of the next message and moves the clock forward by `delta`.
+    pub fn tick(&mut self) -> u32 {
+        let now = self.now;
+        self.now = self.now.saturating_add(self.delta);
+        now
+    }
+
+    /// Moves the clock forward by `seconds` in addition to the regular delta, e.g. to pass a timeout.
+    pub fn advance(&mut self, seconds: u32) {
+        self.now = self.now.saturating_add(seconds);
+    }
+}
+
+/// Calls the contract and attributes the gas of each command to its source line from `debug_info`
+/// of the params. Returns the gas per line in the folded stacks format of flamegraph tools:
+/// `file:line gas`, one line per source line. The `trace_callback` of the params, if any, is still
+/// called for each command.
+pub fn call_contract_gas_folded<F>(
+    addr: MsgAddressInt,
+    state_init: StateInit,
+    mut params: TestCallParams<F>,
+) -> Result<String>
+    where F: Fn(SliceData, bool)
+{
+    let debug_info = params.debug_info.clone();
+    let gas_per_line = Arc::new(Mutex::new(BTreeMap::<String, i64>::new()));
+    let collector = gas_per_line.clone();
+    let user_callback = params.trace_callback.take();
+    params.trace_callback = Some(Box::new(move |engine: &Engine, info: &EngineTraceInfo| {
+        if let Some(ref callback) = user_callback {
+            callback(engine, info);
+        }
+        if info.info_type == EngineTraceInfoType::Dump {
+            return
+        }
+        let position = debug_info.as_ref()
+            .and_then(|debug_info| debug_info.get(&info.cmd_code.cell().repr_hash())?.get(&info.cmd_code.pos()))
+            .map(|pos| format!("{}:{}", pos.filename, pos.line))
+            .unwrap_or_else(|| "<unknown>".to_string());
+        *collector.lock().unwrap().entry(position).or_default() += info.gas_cmd;
+    }));
+    call_contract(addr, state_init, params)?;
+    let folded = gas_per_line.lock().unwrap().iter()
+        .map(|(position, gas)| format!("{} {}\n", position, gas))
+        .collect();
+    Ok(folded)
+}
+
+/// Calls the contract with each of the messages in turn, the state after a successful call is used
+/// for the next one. `now` of each message is taken from `clock`. Returns the resulting state and
+/// the exit code and success flag of each call.
+pub fn call_sequence<'a, F>(
+    addr: MsgAddressInt,
+    mut state_init: StateInit,
+    calls: Vec<TestCallParams<'a, F>>,
+    clock: &mut ScenarioClock,
+) -> Result<(StateInit, Vec<(i32, bool)>)>
+    where F: Fn(SliceData, bool)
+{
+    let mut results = Vec::with_capacity(calls.len());
+    for mut params in calls {
+        params.msg_info.now = clock.tick();
+        let (exit_code, new_state, is_success) = call_contract(addr.clone(), state_init.clone(), params)?;
+        if is_success {
+            state_init = new_state;
+        }
+        results.push((exit_code, is_success));
+    }
+    Ok((state_init, results))
+}
+
+/// Same as `call_contract`, but also returns the gas spent within the gas credit and the paid gas.
+pub fn call_contract_ex<F>(
+    addr: MsgAddressInt,
+    state_init: StateInit,
+    params: TestCallParams<F>,
+) -> Result<(i32, StateInit, bool, GasUsage)>
+    where F: Fn(SliceData, bool)
 {
     let func_selector = match params.msg_info.balance {
         Some(_) => 0,
@@ -342,9 +769,13 @@ pub fn call_contract<F>(
     };
 
     let msg = create_inbound_msg(func_selector, &params.msg_info, addr.address())?;
+    let capabilities = match params.vm_version {
+        Some(version) => version.apply(params.capabilities),
+        None => params.capabilities,
+    };
 
     if !log_enabled!(Error) {
-        init_logger(params.trace_level == TraceLevel::Full)?;
+        init_logger(params.trace_level == TraceLevel::Full || params.trace_level == TraceLevel::StackDiff)?;
     }
 
     let mut state_init = state_init;
@@ -355,10 +786,11 @@ pub fn call_contract<F>(
         data,
         code.clone().into_cell(),
         addr.clone(),
-        params.msg_info.now,
+        params.block_time_override.unwrap_or(params.msg_info.now),
         smc_balance,
         params.config,
-        params.capabilities
+        capabilities,
+        params.global_id,
     )?;
 
     let mut stack = Stack::new();
@@ -400,26 +832,61 @@ pub fn call_contract<F>(
             .push(int!(func_selector));
     }
 
-    let gas = if let Some(gas_limit) = params.gas_limit {
-        let mut tmp_gas = Gas::test();
-        tmp_gas.new_gas_limit(gas_limit);
-        tmp_gas
-    } else {
-        Gas::test()
+    let gas = match params.gas_limit {
+        Some(GasLimit::Limit(gas_limit)) => {
+            let mut tmp_gas = Gas::test();
+            tmp_gas.new_gas_limit(gas_limit);
+            tmp_gas
+        }
+        Some(GasLimit::Credit(gas_credit)) => {
+            let test_gas = Gas::test();
+            Gas::new(0, gas_credit, test_gas.get_gas_limit_max(), test_gas.get_gas_price())
+        }
+        None => Gas::test()
     };
+    let gas_credit = gas.get_gas_credit();
 
-    println!("Engine capabilities: {}", params.capabilities);
+    println!("Engine capabilities: {}", capabilities);
     let mut engine = Engine::with_capabilities(
-        params.capabilities
+        capabilities
     ).setup_with_libraries(
         code, Some(registers), Some(stack), Some(gas), vec![]
     );
     engine.set_trace(0);
     let debug_info = params.debug_info;
-    match params.trace_level {
-        TraceLevel::Full => engine.set_trace_callback(move |engine, info| { trace_callback(engine, info, true, &debug_info); }),
-        TraceLevel::Minimal => engine.set_trace_callback(move |engine, info| { trace_callback_minimal(engine, info, &debug_info); }),
-        TraceLevel::None => {}
+    let level_callback: Option<TraceCallback> = match params.trace_level {
+        TraceLevel::Full => Some(Box::new(move |engine: &Engine, info: &EngineTraceInfo| { trace_callback(engine, info, true, &debug_info); })),
+        TraceLevel::StackDiff => {
+            let prev_stack = Mutex::new(Vec::new());
+            Some(Box::new(move |engine: &Engine, info: &EngineTraceInfo| { trace_callback_stack_diff(engine, info, &debug_info, &prev_stack); }))
+        }
+        TraceLevel::Minimal => Some(Box::new(move |engine: &Engine, info: &EngineTraceInfo| { trace_callback_minimal(engine, info, &debug_info); })),
+        TraceLevel::None => None,
+    };
+    let level_callback: Option<TraceCallback> = match (level_callback, params.trace_filter) {
+        (Some(callback), Some(filter)) => Some(Box::new(move |engine: &Engine, info: &EngineTraceInfo| {
+            if filter.contains(&info.cmd_code.cell().repr_hash()) {
+                callback(engine, info);
+            }
+        })),
+        (callback, _) => callback,
+    };
+    let user_callback = params.trace_callback;
+    // the credit is over once the contract executes ACCEPT or SETGASLIMIT
+    let credit_used = Arc::new(Mutex::new(None));
+    if gas_credit > 0 || level_callback.is_some() || user_callback.is_some() {
+        let credit_used = credit_used.clone();
+        engine.set_trace_callback(move |engine, info| {
+            if gas_credit > 0 && engine.get_gas().get_gas_credit() == 0 {
+                credit_used.lock().unwrap().get_or_insert(info.gas_used);
+            }
+            if let Some(ref callback) = level_callback {
+                callback(engine, info);
+            }
+            if let Some(ref callback) = user_callback {
+                callback(engine, info);
+            }
+        });
     }
     let exit_code = match engine.execute() {
         Err(exc) => match tvm_exception(exc) {
@@ -435,7 +902,19 @@ pub fn call_contract<F>(
     let is_vm_success = engine.get_committed_state().is_committed();
     println!("TVM terminated with exit code {}", exit_code);
     println!("Computing phase is success: {}", is_vm_success);
-    println!("Gas used: {}", engine.get_gas().get_gas_used());
+    if !is_vm_success {
+        println!("Active capabilities: {}", Capabilities::from(capabilities));
+    }
+    let gas_used = engine.get_gas().get_gas_used();
+    let gas_usage = match *credit_used.lock().unwrap() {
+        Some(credit) => GasUsage { credit, paid: gas_used - credit },
+        None if gas_credit > 0 => GasUsage { credit: gas_used, paid: 0 },
+        None => GasUsage { credit: 0, paid: gas_used },
+    };
+    println!("Gas used: {}", gas_used);
+    if gas_credit > 0 {
+        println!("Gas used within credit: {}, paid: {}", gas_usage.credit, gas_usage.paid);
+    }
     println!();
     println!("{}", engine.dump_stack("Post-execution stack state", false));
     println!("{}", engine.dump_ctrls(false));
@@ -451,7 +930,184 @@ pub fn call_contract<F>(
         };
     }
 
-    Ok((exit_code, state_init, is_vm_success))
+    Ok((exit_code, state_init, is_vm_success, gas_usage))
+}
+
+fn balance_to_string(balance: &CurrencyCollection) -> String {
+    if balance.other.is_empty() {
+        return balance.grams.to_string()
+    }
+    let mut extra = Vec::new();
+    balance.other.iterate_with_keys(|key: u32, value| {
+        extra.push(format!("\"{}\": {}", key, value));
+        Ok(true)
+    }).ok();
+    format!("{{ \"main\": {}, \"extra\": {{ {} }} }}", balance.grams, extra.join(", "))
+}
+
+/// Reruns the transaction defined by the dumped account state and its inbound message.
+pub fn replay_transaction(
+    account_file: &str,
+    message_file: &str,
+    config_file: Option<&str>,
+    trace_level: TraceLevel,
+) -> Result<(i32, StateInit, bool)> {
+    let account = Account::construct_from_file(account_file)?;
+    let addr = account.get_addr().cloned()
+        .ok_or_else(|| format_err!("account {} has no address", account_file))?;
+    let state_init = account.state_init().cloned()
+        .ok_or_else(|| format_err!("account {} doesn't contain stateInit", account_file))?;
+    let balance = account.balance().map(balance_to_string);
+
+    let msg = Message::construct_from_file(message_file)?;
+    let (value, src, now, bounced) = if let Some(header) = msg.int_header() {
+        let src = match header.src {
+            MsgAddressIntOrNone::Some(ref src) => Some(src.to_string()),
+            MsgAddressIntOrNone::None => None,
+        };
+        (Some(balance_to_string(&header.value)), src, header.created_at.as_u32(), header.bounced)
+    } else if msg.ext_in_header().is_some() {
+        (None, None, get_now(), false)
+    } else {
+        bail!("message {} is not an inbound message", message_file)
+    };
+
+    let config = config_file.and_then(load_config);
+    let capabilities = config_capabilities(config.as_ref());
+    call_contract(addr, state_init, TestCallParams {
+        balance: balance.as_deref(),
+        msg_info: MsgInfo {
+            balance: value.as_deref(),
+            src: src.as_deref(),
+            now,
+            bounced,
+            body: msg.body(),
+        },
+        config,
+        key_file: None,
+        ticktock: None,
+        gas_limit: None,
+        action_decoder: None::<fn(SliceData, bool)>,
+        trace_level,
+        debug_info: None,
+        trace_callback: None,
+        capabilities,
+        vm_version: None,
+        block_time_override: None,
+        trace_filter: None,
+        global_id: None,
+    })
+}
+
+/// Runs an ABI getter of the contract saved in `contract_file` as a get-method of the workchain `wc`.
+/// The parameters are pushed to the stack in the order of the ABI inputs followed by the function id,
+/// the resulting stack is decoded against the ABI outputs. Integers, booleans, cells and addresses are supported.
+pub fn run_get_method(
+    contract_file: &str,
+    abi_file: &str,
+    method: &str,
+    params: &str,
+    wc: i8,
+) -> Result<Value> {
+    let contract = load_abi_contract(&load_abi_json_string(abi_file)?)?;
+    let function = contract.functions().get(method)
+        .ok_or_else(|| format_err!("getter {} is not found in {}", method, abi_file))?;
+    let params: Value = serde_json::from_str(params)
+        .map_err(|e| format_err!("failed to parse parameters of getter {}: {}", method, e))?;
+    let mut stack_args = vec![];
+    for param in &function.inputs {
+        let value = params.get(&param.name)
+            .ok_or_else(|| format_err!("parameter {} of getter {} is missing", param.name, method))?;
+        stack_args.push(json_to_stack_item(&param.kind, value)
+            .map_err(|e| format_err!("parameter {} of getter {}: {}", param.name, method, e))?);
+    }
+    let state_init = load_from_file(contract_file)?;
+    let stack = run_get_method_ex(state_init, wc, function.get_input_id() as i64, stack_args)?;
+    if stack.len() != function.outputs.len() {
+        bail!("getter {} returned {} values instead of {}", method, stack.len(), function.outputs.len());
+    }
+    let mut output = serde_json::Map::new();
+    for (param, item) in function.outputs.iter().zip(stack.iter()) {
+        let value = stack_item_to_json(&param.kind, item)
+            .map_err(|e| format_err!("output {} of getter {}: {}", param.name, method, e))?;
+        output.insert(param.name.clone(), value);
+    }
+    Ok(Value::Object(output))
+}
+
+fn json_to_stack_item(kind: &ParamType, value: &Value) -> Result<StackItem> {
+    let item = match (kind, value) {
+        (ParamType::Uint(_) | ParamType::Int(_), Value::Number(number)) =>
+            StackItem::Integer(Arc::new(IntegerData::from_str_radix(&number.to_string(), 10)?)),
+        (ParamType::Uint(_) | ParamType::Int(_), Value::String(string)) => {
+            let int = match string.strip_prefix("0x") {
+                Some(hex) => IntegerData::from_str_radix(hex, 16)?,
+                None => IntegerData::from_str_radix(string, 10)?,
+            };
+            StackItem::Integer(Arc::new(int))
+        }
+        (ParamType::Bool, Value::Bool(flag)) => int!(if *flag { -1 } else { 0 }),
+        (ParamType::Cell, Value::String(string)) => StackItem::Cell(read_single_root_boc(base64::decode(string)?)?),
+        (ParamType::Address, Value::String(string)) => {
+            let cell = MsgAddressInt::from_str(string)?.serialize()?;
+            StackItem::Slice(SliceData::load_cell(cell)?)
+        }
+        _ => bail!("value {} of type {} is not supported", value, kind),
+    };
+    Ok(item)
+}
+
+fn stack_item_to_json(kind: &ParamType, item: &StackItem) -> Result<Value> {
+    let value = match kind {
+        ParamType::Uint(_) | ParamType::Int(_) => Value::String(item.as_integer()?.to_string()),
+        ParamType::Bool => Value::Bool(item.as_bool()?),
+        ParamType::Cell => Value::String(base64::encode(write_boc(item.as_cell()?)?)),
+        ParamType::Address => Value::String(MsgAddressInt::construct_from(&mut item.as_slice()?.clone())?.to_string()),
+        _ => bail!("type {} is not supported", kind),
+    };
+    Ok(value)
+}
+
+/// Runs a get-method of the contract by its numeric id, e.g. FunC's `seqno` getter with the id 85143.
+/// The arguments are pushed to the stack followed by the method id, the resulting stack is returned.
+/// The code of the contract should dispatch get-methods by the id on top of the stack.
+pub fn run_get_method_by_id(contract_file: &str, method_id: i32, stack_args: Vec<StackItem>) -> Result<Vec<StackItem>> {
+    run_get_method_ex(load_from_file(contract_file)?, 0, method_id as i64, stack_args)
+}
+
+fn run_get_method_ex(state_init: StateInit, wc: i8, method_id: i64, stack_args: Vec<StackItem>) -> Result<Vec<StackItem>> {
+    let hash = state_init.hash()?;
+    let addr = MsgAddressInt::with_standart(None, wc, SliceData::from_raw(hash.as_slice().to_vec(), 256))?;
+    let (code, data) = load_code_and_data(&state_init);
+    let registers = initialize_registers(
+        data,
+        code.clone().into_cell(),
+        addr,
+        get_now(),
+        CurrencyCollection::default(),
+        None,
+        DEFAULT_CAPABILITIES,
+        None,
+    )?;
+    let mut stack = Stack::new();
+    for item in stack_args {
+        stack.push(item);
+    }
+    stack.push(int!(method_id));
+    let mut engine = Engine::with_capabilities(DEFAULT_CAPABILITIES).setup_with_libraries(
+        code, Some(registers), Some(stack), Some(Gas::test()), vec![]
+    );
+    let exit_code = match engine.execute() {
+        Err(exc) => match tvm_exception(exc) {
+            Ok(exc) => exc.exception_or_custom_code(),
+            Err(e) => bail!("get-method {} failed: {}", method_id, e),
+        },
+        Ok(code) => code,
+    };
+    if exit_code != 0 && exit_code != 1 {
+        bail!("get-method {} failed with exit code {}", method_id, exit_code);
+    }
+    Ok(engine.stack().iter().cloned().collect())
 }
 
 #[cfg(test)]
@@ -492,6 +1148,61 @@ mod tests {
         println!("SendMsg action:\n{}", msg_printer(&msg).unwrap_or("Undefined".to_string()));
     }
 
+    #[test]
+    fn test_bounced_msg_info() {
+        let body = SliceData::load_cell(create_inbound_body(10, 20, 0x11223344).unwrap()).unwrap();
+        let original = create_internal_msg(
+            MsgAddressInt::with_standart(None, 0, [0x11; 32].into()).unwrap(),
+            MsgAddressInt::with_standart(None, 0, [0x22; 32].into()).unwrap(),
+            CurrencyCollection::with_grams(12345678),
+            1,
+            2,
+            Some(body.clone()),
+            false,
+        );
+        let msg_info = MsgInfo::bounced_from(&original, Some("12345678"), None, 3).unwrap();
+        assert!(msg_info.bounced);
+        let mut bounced_body = msg_info.body.unwrap();
+        assert_eq!(bounced_body.remaining_bits(), 32 + body.remaining_bits());
+        assert_eq!(bounced_body.get_next_u32().unwrap(), 0xffffffff);
+        assert_eq!(bounced_body.get_bytestring(0), body.get_bytestring(0));
+
+        let ext = create_external_inbound_msg(
+            MsgAddressExt::with_extern(SliceData::from_raw(vec![0x55; 8], 64)).unwrap(),
+            MsgAddressInt::with_standart(None, 0, [0x11; 32].into()).unwrap(),
+            None,
+        );
+        assert!(MsgInfo::bounced_from(&ext, None, None, 3).is_err());
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let caps = Capabilities::from_list(&[GlobalCapabilities::CapMycode, GlobalCapabilities::CapInitCodeHash]);
+        assert_eq!(caps.mask(), 0x500);
+        assert!(caps.has(GlobalCapabilities::CapMycode));
+        assert!(!caps.has(GlobalCapabilities::CapSetLibCode));
+
+        assert_eq!(Capabilities::from_str("CapMycode, capinitcodehash").unwrap(), caps);
+        assert_eq!(Capabilities::from_str("0x42E").unwrap().mask(), 0x42E);
+        assert_eq!(Capabilities::from_str("CapMycode,0x2").unwrap().mask(), 0x402);
+        assert!(Capabilities::from_str("CapUnknown").is_err());
+
+        assert_eq!(caps.to_string(), "CapInitCodeHash, CapMycode");
+        assert_eq!(Capabilities::new().to_string(), "none");
+        assert_eq!(Capabilities::from(0x400 | (1 << 63)).to_string(), "CapMycode, 0x8000000000000000");
+    }
+
+    #[test]
+    fn test_balance_to_string() {
+        let mut balance = CurrencyCollection::with_grams(100);
+        assert_eq!(balance_to_string(&balance), "100");
+        balance.set_other(0, 33).unwrap();
+        balance.set_other(50, 99).unwrap();
+        let (main, decoded) = decode_balance(Some(&balance_to_string(&balance))).unwrap();
+        assert_eq!(main, 100);
+        assert_eq!(decoded, balance);
+    }
+
     #[test]
     fn test_decode_balance() {
         let (main, balance) = decode_balance(Some(r#"{ "main": 100, "extra": {"0": 33, "50": 99} }"#)).unwrap();
@@ -528,4 +1239,265 @@ mod tests {
         let err = decode_balance(Some(r#"{ "main": 0, extra: {"0": qwe} }"#));
         assert_eq!(err.is_err(), true);
     }
+
+    #[test]
+    fn test_stack_diff() {
+        let stack = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(stack_diff(&stack(&["1", "2"]), &stack(&["1", "2"])), "");
+        assert_eq!(stack_diff(&stack(&["1"]), &stack(&["1", "2"])), "+ 2\n");
+        assert_eq!(stack_diff(&stack(&["1", "2", "3"]), &stack(&["1"])), "- 3\n- 2\n");
+        assert_eq!(stack_diff(&stack(&["1", "2"]), &stack(&["1", "5"])), "- 2\n+ 5\n");
+    }
+
+    #[test]
+    fn test_source_for() {
+        let hash = UInt256::from([1; 32]);
+        let pos = |line| DbgPos { filename: "test.code".to_string(), line, line_code: line };
+        let mut node = std::collections::BTreeMap::new();
+        node.insert(16, pos(4));
+        node.insert(0, pos(3));
+        let mut debug_info = DbgInfo::default();
+        debug_info.insert(hash.clone(), node);
+        assert_eq!(source_for(&debug_info, &hash), Some(&pos(3)));
+        assert_eq!(source_for(&debug_info, &UInt256::default()), None);
+    }
+
+    #[test]
+    fn test_gas_usage() {
+        let call = |code: &str| {
+            let mut state = StateInit::default();
+            state.set_code(ton_labs_assembler::compile_code_to_cell(code).unwrap());
+            state.set_data(Cell::default());
+            let addr = MsgAddressInt::with_standart(None, 0, AccountId::from_str(&"0".repeat(64)).unwrap()).unwrap();
+            let (exit_code, _, _, gas) = call_contract_ex(addr, state, TestCallParams {
+                balance: None,
+                msg_info: MsgInfo { balance: None, src: None, now: 1, bounced: false, body: None },
+                config: None,
+                key_file: None,
+                ticktock: None,
+                gas_limit: Some(GasLimit::Credit(10000)),
+                action_decoder: None::<fn(SliceData, bool)>,
+                trace_level: TraceLevel::None,
+                debug_info: None,
+                trace_callback: None,
+                capabilities: 0x42E,
+                vm_version: None,
+                block_time_override: None,
+                trace_filter: None,
+                global_id: None,
+            }).unwrap();
+            assert_eq!(exit_code, 0);
+            gas
+        };
+        let accepted = call("PUSHINT 1\nDROP\nACCEPT\nPUSHINT 2\nDROP\n");
+        assert!(accepted.credit > 0);
+        assert!(accepted.paid > 0);
+        let rejected = call("PUSHINT 1\nDROP\n");
+        assert!(rejected.credit > 0);
+        assert_eq!(rejected.paid, 0);
+    }
+
+    #[test]
+    fn test_call_sequence() {
+        let mut state = StateInit::default();
+        state.set_code(ton_labs_assembler::compile_code_to_cell("ACCEPT\nNOW\nNEWC\nSTU 32\nENDC\nPOPROOT\n").unwrap());
+        state.set_data(Cell::default());
+        let addr = MsgAddressInt::with_standart(None, 0, AccountId::from_str(&"0".repeat(64)).unwrap()).unwrap();
+        let params = || TestCallParams {
+            balance: None,
+            msg_info: MsgInfo { balance: None, src: None, now: 0, bounced: false, body: None },
+            config: None,
+            key_file: None,
+            ticktock: None,
+            gas_limit: Some(GasLimit::Credit(10000)),
+            action_decoder: None::<fn(SliceData, bool)>,
+            trace_level: TraceLevel::None,
+            debug_info: None,
+            trace_callback: None,
+            capabilities: 0x42E,
+            vm_version: None,
+            block_time_override: None,
+            trace_filter: None,
+            global_id: None,
+        };
+        let mut clock = ScenarioClock::new(1000, 60);
+        let (state, results) = call_sequence(addr.clone(), state, vec![params(), params()], &mut clock).unwrap();
+        assert_eq!(results, vec![(0, true), (0, true)]);
+        let now = |state: &StateInit| SliceData::load_cell(state.data.clone().unwrap()).unwrap().get_next_u32().unwrap();
+        assert_eq!(now(&state), 1060);
+        assert_eq!(clock.now(), 1120);
+
+        clock.advance(3600);
+        let (state, _) = call_sequence(addr, state, vec![params()], &mut clock).unwrap();
+        assert_eq!(now(&state), 4720);
+    }
+
+    #[test]
+    fn test_block_time_override() {
+        let mut state = StateInit::default();
+        state.set_code(ton_labs_assembler::compile_code_to_cell("ACCEPT\nNOW\nNEWC\nSTU 32\nENDC\nPOPROOT\n").unwrap());
+        state.set_data(Cell::default());
+        let addr = MsgAddressInt::with_standart(None, 0, AccountId::from_str(&"0".repeat(64)).unwrap()).unwrap();
+        let (_, state, is_success) = call_contract(addr, state, TestCallParams {
+            balance: None,
+            msg_info: MsgInfo { balance: None, src: None, now: 1, bounced: false, body: None },
+            config: None,
+            key_file: None,
+            ticktock: None,
+            gas_limit: Some(GasLimit::Credit(10000)),
+            action_decoder: None::<fn(SliceData, bool)>,
+            trace_level: TraceLevel::None,
+            debug_info: None,
+            trace_callback: None,
+            capabilities: 0x42E,
+            vm_version: None,
+            block_time_override: Some(2000000000),
+            trace_filter: None,
+            global_id: None,
+        }).unwrap();
+        assert!(is_success);
+        let now = SliceData::load_cell(state.data.unwrap()).unwrap().get_next_u32().unwrap();
+        assert_eq!(now, 2000000000);
+    }
+
+    #[test]
+    fn test_global_id() {
+        let mut state = StateInit::default();
+        state.set_code(ton_labs_assembler::compile_code_to_cell(
+            "ACCEPT\nPUSHINT 19\nCONFIGPARAM\nTHROWIFNOT 100\nCTOS\nLDI 32\nENDS\nNEWC\nSTI 32\nENDC\nPOPROOT\n"
+        ).unwrap());
+        state.set_data(Cell::default());
+        let addr = MsgAddressInt::with_standart(None, 0, AccountId::from_str(&"0".repeat(64)).unwrap()).unwrap();
+        let params = |global_id| TestCallParams {
+            balance: None,
+            msg_info: MsgInfo { balance: None, src: None, now: 1, bounced: false, body: None },
+            config: None,
+            key_file: None,
+            ticktock: None,
+            gas_limit: Some(GasLimit::Credit(10000)),
+            action_decoder: None::<fn(SliceData, bool)>,
+            trace_level: TraceLevel::None,
+            debug_info: None,
+            trace_callback: None,
+            capabilities: 0x42E,
+            vm_version: None,
+            block_time_override: None,
+            trace_filter: None,
+            global_id,
+        };
+        for global_id in [-239, 42] {
+            let (_, state, is_success) = call_contract(addr.clone(), state.clone(), params(Some(global_id))).unwrap();
+            assert!(is_success);
+            let value = SliceData::load_cell(state.data.unwrap()).unwrap().get_next_i32().unwrap();
+            assert_eq!(value, global_id);
+        }
+        let (exit_code, _, is_success) = call_contract(addr, state, params(None)).unwrap();
+        assert!(!is_success);
+        assert_eq!(exit_code, 100);
+    }
+
+    #[test]
+    fn test_method_cells() {
+        let parser = crate::parser::ParseEngine::new(vec![std::path::Path::new("tests/test_unused_private.code")], None).unwrap();
+        let mut prog = crate::program::Program::new(parser).unwrap();
+        prog.compile_asm(false).unwrap();
+        let symbols = prog.symbols();
+        for symbol in &symbols {
+            let cells = method_cells(&prog.dbgmap, &symbols, &symbol.name).unwrap();
+            assert!(!cells.is_empty(), "no cells of {}", symbol.name);
+        }
+        let used = method_cells(&prog.dbgmap, &symbols, "used").unwrap();
+        let unused = method_cells(&prog.dbgmap, &symbols, "unused").unwrap();
+        assert!(used.is_disjoint(&unused));
+        assert!(method_cells(&prog.dbgmap, &symbols, "missing").is_err());
+    }
+
+    #[test]
+    fn test_call_contract_gas_folded() {
+        let mut prog = crate::program::Program::from_str(
+            ".internal-alias :main_external, -1\n.internal :main_external\nACCEPT\nPUSHINT 1\nDROP\n", None
+        ).unwrap();
+        prog.set_verbosity(crate::program::Verbosity::Quiet);
+        let file_name = prog.compile_to_file_ex(0, Some("tests/gas_folded.tvc"), None).unwrap();
+        let addr = MsgAddressInt::with_standart(None, 0, AccountId::from_str(&"0".repeat(64)).unwrap()).unwrap();
+        let steps = Arc::new(Mutex::new(0));
+        let counter = steps.clone();
+        let folded = call_contract_gas_folded(addr, load_from_file(&file_name).unwrap(), TestCallParams {
+            balance: None,
+            msg_info: MsgInfo { balance: None, src: None, now: 1, bounced: false, body: None },
+            config: None,
+            key_file: None,
+            ticktock: None,
+            gas_limit: None,
+            action_decoder: None::<fn(SliceData, bool)>,
+            trace_level: TraceLevel::None,
+            debug_info: Some(prog.dbgmap.clone()),
+            trace_callback: Some(Box::new(move |_: &Engine, _: &EngineTraceInfo| *counter.lock().unwrap() += 1)),
+            capabilities: 0x42E,
+            vm_version: None,
+            block_time_override: None,
+            trace_filter: None,
+            global_id: None,
+        }).unwrap();
+        assert!(folded.lines().any(|line| line.starts_with("<string>:3 ")), "{}", folded);
+        assert!(*steps.lock().unwrap() > 0);
+        for line in folded.lines() {
+            let (_, gas) = line.rsplit_once(' ').unwrap();
+            assert!(gas.parse::<i64>().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_vm_version() {
+        let bugfixes = GlobalCapabilities::CapsTvmBugfixes2022 as u64;
+        let stcont = GlobalCapabilities::CapStcontNewFormat as u64;
+        assert_eq!(VmVersion::Initial.apply(0x42E | bugfixes | stcont), 0x42E);
+        assert_eq!(VmVersion::Bugfixes2022.apply(0x42E | stcont), 0x42E | bugfixes);
+        assert_eq!(VmVersion::Current.apply(0x42E), 0x42E | bugfixes | stcont);
+        assert_eq!(VmVersion::from_str("2022").unwrap(), VmVersion::Bugfixes2022);
+        assert!(VmVersion::from_str("v19").is_err());
+    }
+
+    #[test]
+    fn test_run_get_method_by_id() {
+        let code = ton_labs_assembler::compile_code_to_cell("EQINT 85143\nTHROWIFNOT 11\nINC\n").unwrap();
+        let mut state = StateInit::default();
+        state.set_code(code);
+        state.set_data(Cell::default());
+        crate::program::save_to_file(state, Some("tests/get_method_by_id.tvc"), 0, true).unwrap();
+        let stack = run_get_method_by_id("tests/get_method_by_id.tvc", 85143, vec![int!(41)]).unwrap();
+        assert_eq!(stack, vec![int!(42)]);
+        assert!(run_get_method_by_id("tests/get_method_by_id.tvc", 1, vec![int!(41)]).is_err());
+    }
+
+    #[test]
+    fn test_run_get_method() {
+        let abi = r#"{
+            "ABI version": 2,
+            "version": "2.2",
+            "header": [],
+            "functions": [{
+                "name": "sum",
+                "inputs": [{"name": "a", "type": "uint32"}, {"name": "b", "type": "uint32"}],
+                "outputs": [{"name": "value0", "type": "uint64"}, {"name": "flag", "type": "bool"}, {"name": "me", "type": "address"}]
+            }],
+            "events": [],
+            "data": []
+        }"#;
+        std::fs::write("tests/get_method.abi.json", abi).unwrap();
+        let id = load_abi_contract(abi).unwrap().functions()["sum"].get_input_id();
+        let code = ton_labs_assembler::compile_code_to_cell(
+            &format!("PUSHINT {}\nEQUAL\nTHROWIFNOT 11\nADD\nTRUE\nMYADDR\n", id)
+        ).unwrap();
+        let mut state = StateInit::default();
+        state.set_code(code);
+        state.set_data(Cell::default());
+        let hash = state.hash().unwrap();
+        crate::program::save_to_file(state, Some("tests/get_method.tvc"), 0, true).unwrap();
+
+        let output = run_get_method("tests/get_method.tvc", "tests/get_method.abi.json", "sum", r#"{"a": 40, "b": "0x2"}"#, -1).unwrap();
+        let me = MsgAddressInt::with_standart(None, -1, SliceData::from_raw(hash.as_slice().to_vec(), 256)).unwrap();
+        assert_eq!(output, serde_json::json!({"value0": "42", "flag": true, "me": me.to_string()}));
+        assert!(run_get_method("tests/get_method.tvc", "tests/get_method.abi.json", "sum", r#"{"a": 40}"#, -1).is_err());
+    }
 }